@@ -3,8 +3,12 @@ use rusoto_core::Region;
 use serde_json;
 use std::str::FromStr;
 
-use std::collections::HashMap;
-use std::fs::File;
+use std::convert::TryFrom;
+use std::fmt;
+use std::path::PathBuf;
+
+use audit::{AuditConfig, OutputFormat, Severity, Suppressions};
+use input::{InputFormat, ServiceMap};
 
 /// This tool does bulk operations against sub-components in a cluster. Use with great care.
 #[derive(Debug, StructOpt)]
@@ -26,6 +30,7 @@ pub struct Args {
 }
 
 #[derive(Debug, StructOpt)]
+#[allow(clippy::enum_variant_names)]
 pub enum EcsCommand {
     /// Do operations on all services within a cluster
     #[structopt(name = "services")]
@@ -34,6 +39,175 @@ pub enum EcsCommand {
         #[structopt(subcommand)]
         command: ServicesCommand,
     },
+    /// Do operations on clusters
+    #[structopt(name = "clusters")]
+    ClustersCommand {
+        /// Sub commands
+        #[structopt(subcommand)]
+        command: ClustersCommand,
+    },
+    /// Do operations on the tasks within a cluster
+    #[structopt(name = "tasks")]
+    TasksCommand {
+        /// Sub commands
+        #[structopt(subcommand)]
+        command: TasksCommand,
+    },
+    /// Do operations on task definitions
+    #[structopt(name = "task-definitions")]
+    TaskDefinitionsCommand {
+        /// Sub commands
+        #[structopt(subcommand)]
+        command: TaskDefinitionsCommand,
+    },
+}
+
+#[derive(Debug, StructOpt)]
+pub enum TaskDefinitionsCommand {
+    /// Active revisions of each family, split into those the clusters' services and tasks use and the rest
+    #[structopt(name = "list")]
+    List {
+        #[structopt(flatten)]
+        targets: Targets,
+    },
+    /// Compare two task definitions setting by setting and container by container
+    #[structopt(name = "diff")]
+    Diff {
+        /// The AWS region
+        region: Region,
+        /// The first task definition, as `family:revision` or an ARN
+        a: String,
+        /// The second task definition, as `family:revision` or an ARN
+        b: String,
+    },
+    /// Deregister active revisions that no service or running task in the regions uses. Tasks
+    /// started later, e.g. by scheduled rules, may still need a deregistered revision
+    #[structopt(name = "deregister-unused")]
+    DeregisterUnused {
+        #[structopt(flatten)]
+        targets: Targets,
+        /// Always keep this many of the newest revisions of each family
+        #[structopt(long = "keep")]
+        keep: usize,
+        /// Only count revisions as in use when the given clusters use them, rather than any
+        /// cluster in the regions
+        #[structopt(long = "only-given-clusters")]
+        only_given_clusters: bool,
+        /// Only print the revisions that would be deregistered
+        #[structopt(long = "dry-run")]
+        dry_run: bool,
+        /// Deregister without asking for confirmation
+        #[structopt(long = "yes")]
+        yes: bool,
+    },
+    /// Register a new revision of a task definition with a different container image
+    #[structopt(name = "bump-image")]
+    BumpImage {
+        /// The AWS region
+        region: Region,
+        /// The task definition to copy, as `family`, `family:revision` or an ARN
+        task_definition: String,
+        /// The new image, or just a `:tag` to keep the current repository
+        image: String,
+        /// The container to change, needed when there is more than one
+        #[structopt(long = "container")]
+        container: Option<String>,
+    },
+}
+
+#[derive(Debug, StructOpt)]
+pub enum TasksCommand {
+    /// Status, health, stop reason and container exit codes of tasks
+    #[structopt(name = "list")]
+    List {
+        /// The cluster name
+        cluster: String,
+        /// The AWS region
+        region: Region,
+        /// Only list the tasks of this service
+        #[structopt(long = "service")]
+        service: Option<String>,
+        /// List recently stopped tasks instead of running ones
+        #[structopt(long = "stopped")]
+        stopped: bool,
+    },
+    /// Stop the running tasks that match every given filter
+    #[structopt(name = "stop")]
+    Stop {
+        /// The cluster name
+        cluster: String,
+        /// The AWS region
+        region: Region,
+        #[structopt(flatten)]
+        filter: TaskFilter,
+        /// The stop reason recorded on each task
+        #[structopt(long = "reason", default_value = "Stopped by ecs-manage")]
+        reason: String,
+        /// Sleep between each task this many milliseconds, so a service has time to replace
+        /// the tasks it loses
+        #[structopt(long = "sleep", default_value = "10000")]
+        sleep: u64,
+        /// Only print the tasks that would be stopped
+        #[structopt(long = "dry-run")]
+        dry_run: bool,
+        /// Stop without asking for confirmation
+        #[structopt(long = "yes")]
+        yes: bool,
+    },
+}
+
+/// Which running tasks to operate on
+#[derive(Debug, StructOpt)]
+pub struct TaskFilter {
+    /// Only tasks of this service
+    #[structopt(long = "service")]
+    pub service: Option<String>,
+    /// Only tasks on this container instance (id or ARN)
+    #[structopt(long = "container-instance")]
+    pub container_instance: Option<String>,
+    /// Only tasks started more than this many hours ago
+    #[structopt(long = "older-than-hours")]
+    pub older_than_hours: Option<f64>,
+    /// Only tasks running a different task definition than their service currently deploys
+    #[structopt(long = "old-revision")]
+    pub old_revision: bool,
+}
+
+impl TaskFilter {
+    pub fn is_empty(&self) -> bool {
+        self.service.is_none()
+            && self.container_instance.is_none()
+            && self.older_than_hours.is_none()
+            && !self.old_revision
+    }
+}
+
+#[derive(Debug, StructOpt)]
+pub enum ClustersCommand {
+    /// List the clusters in each region
+    #[structopt(name = "list")]
+    List {
+        /// The AWS regions, comma separated
+        region: RegionList,
+    },
+    /// Capacity, task counts and container instance versions of clusters
+    #[structopt(name = "info")]
+    Info {
+        #[structopt(flatten)]
+        targets: Targets,
+    },
+    /// Show the settings in which two clusters differ
+    #[structopt(name = "compare")]
+    Compare {
+        /// The source cluster name
+        source_cluster: String,
+        /// The source AWS region
+        source_region: Region,
+        /// The destination cluster name
+        destination_cluster: String,
+        /// The destination AWS region
+        destination_region: Region,
+    },
 }
 
 #[derive(Debug, StructOpt)]
@@ -41,18 +215,87 @@ pub enum ServicesCommand {
     /// Useful information about services
     #[structopt(name = "info")]
     Info {
+        #[structopt(flatten)]
+        targets: Targets,
+    },
+    /// Services that have issues (mainly null-references)
+    #[structopt(name = "audit")]
+    Audit {
+        #[structopt(flatten)]
+        targets: Targets,
+        /// Only run the audit check with this id (can be repeated)
+        #[structopt(long = "check", raw(number_of_values = "1"))]
+        checks: Vec<String>,
+        /// Skip the audit check with this id (can be repeated)
+        #[structopt(long = "skip-check", raw(number_of_values = "1"))]
+        skip_checks: Vec<String>,
+        /// A path to a JSON file with `enabled`/`disabled` lists of audit check ids and check settings
+        #[structopt(long = "config")]
+        config: Option<AuditConfig>,
+        /// A path to a JSON list of accepted findings, each with a `service` and `check` pattern
+        /// and an optional `expires` date (YYYY-MM-DD) and `reason`
+        #[structopt(long = "suppressions")]
+        suppressions: Option<Suppressions>,
+        /// The format to print findings in
+        #[structopt(
+            long = "output",
+            default_value = "text",
+            raw(
+                possible_values = "&OutputFormat::variants()",
+                case_insensitive = "true"
+            )
+        )]
+        output: OutputFormat,
+        /// Exit with a non-zero status when any finding is at least this severe
+        #[structopt(
+            long = "fail-on",
+            raw(possible_values = "&Severity::variants()", case_insensitive = "true")
+        )]
+        fail_on: Option<Severity>,
+        /// Plan mechanical fixes for findings and apply them after confirmation
+        #[structopt(long = "fix")]
+        fix: bool,
+        /// Apply the --fix plan without asking for confirmation
+        #[structopt(long = "yes")]
+        yes: bool,
+    },
+    /// Record each service's desired count in a state file and scale every service to zero
+    #[structopt(name = "pause")]
+    Pause {
         /// The cluster name
         cluster: String,
         /// The AWS region
         region: Region,
+        /// The state file, `ecs-manage-paused-<region>-<cluster>.json` by default
+        #[structopt(long = "state", parse(from_os_str))]
+        state: Option<PathBuf>,
+        /// Sleep between each service this many milliseconds
+        #[structopt(long = "sleep", default_value = "0")]
+        sleep: u64,
     },
-    /// Services that have issues (mainly null-references)
-    #[structopt(name = "audit")]
-    Audit {
+    /// Restore the desired counts recorded by `pause`, then remove the state file
+    #[structopt(name = "resume")]
+    Resume {
         /// The cluster name
         cluster: String,
         /// The AWS region
         region: Region,
+        /// The state file, `ecs-manage-paused-<region>-<cluster>.json` by default
+        #[structopt(long = "state", parse(from_os_str))]
+        state: Option<PathBuf>,
+        /// Sleep between each service this many milliseconds
+        #[structopt(long = "sleep", default_value = "0")]
+        sleep: u64,
+    },
+    /// Why a service's recently stopped tasks stopped, counted by reason and exit code
+    #[structopt(name = "stopped-tasks")]
+    StoppedTasks {
+        /// The cluster name
+        cluster: String,
+        /// The AWS region
+        region: Region,
+        /// The service name
+        service: String,
     },
     /// List services that are in source_cluster, but not in destination cluster (by name)
     #[structopt(name = "compare")]
@@ -83,10 +326,8 @@ pub enum ServicesCommand {
     /// Export properties of services in a format that `update` understands
     #[structopt(name = "export")]
     Export {
-        /// The cluster name
-        cluster: String,
-        /// The AWS region
-        region: Region,
+        #[structopt(flatten)]
+        targets: Targets,
         #[structopt(raw(
             possible_values = "&ServiceProperty::variants()",
             case_insensitive = "true"
@@ -105,9 +346,174 @@ pub enum ServicesCommand {
         /// Sleep between each service this many milliseconds
         #[structopt(long = "sleep", default_value = "0")]
         sleep: u64,
+        /// Refuse to make changes when a mapping file leaves out services in the cluster,
+        /// or names services that aren't in it
+        #[structopt(long = "strict")]
+        strict: bool,
+        /// The format of the mapping file, by default taken from its extension
+        /// (`.yaml`, `.yml` or `.toml`), or JSON
+        #[structopt(
+            long = "format",
+            raw(
+                possible_values = "&InputFormat::variants()",
+                case_insensitive = "true"
+            )
+        )]
+        format: Option<InputFormat>,
+    },
+    /// Create, update and delete services so a cluster matches a manifest, which names the
+    /// cluster and region and declares the cluster's services
+    #[structopt(name = "apply")]
+    Apply {
+        /// A path to the manifest, a JSON, YAML or TOML file, or `-` for stdin
+        manifest: String,
+        /// The format of the manifest, by default taken from its extension
+        /// (`.yaml`, `.yml` or `.toml`), or JSON
+        #[structopt(
+            long = "format",
+            raw(
+                possible_values = "&InputFormat::variants()",
+                case_insensitive = "true"
+            )
+        )]
+        format: Option<InputFormat>,
+        /// Delete services in the cluster that the manifest doesn't declare, the way `delete`
+        /// does, once the other changes are made
+        #[structopt(long = "prune")]
+        prune: bool,
+        /// A path to a JSON, YAML or TOML list of services that must never be pruned, which
+        /// may use `*`. Nothing is applied when any of them would be deleted
+        #[structopt(long = "protected")]
+        protected: Option<String>,
+        /// How many minutes to wait for each pruned service's tasks to stop
+        #[structopt(long = "drain-timeout", default_value = "10")]
+        drain_timeout: u64,
+        /// Only print the plan
+        #[structopt(long = "dry-run")]
+        dry_run: bool,
+        /// Apply the plan without asking for confirmation, or for the cluster name to be typed
+        /// when pruning
+        #[structopt(long = "yes")]
+        yes: bool,
+        /// Sleep between each service this many milliseconds
+        #[structopt(long = "sleep", default_value = "0")]
+        sleep: u64,
+    },
+    /// Delete services. Each is scaled to zero and deleted once its tasks have stopped
+    #[structopt(name = "delete")]
+    Delete {
+        /// The cluster name
+        cluster: String,
+        /// The AWS region
+        region: Region,
+        /// The services to delete. Use `*` in a name to match services, e.g. `canary-*`
+        #[structopt(raw(required = "true"))]
+        services: Vec<String>,
+        /// A path to a JSON, YAML or TOML list of services that must never be deleted, which
+        /// may use `*` too. Nothing is deleted when any of them is selected
+        #[structopt(long = "protected")]
+        protected: Option<String>,
+        /// How many minutes to wait for each service's tasks to stop. Services whose tasks
+        /// don't stop in time are left scaled to zero, and the rest are still deleted
+        #[structopt(long = "drain-timeout", default_value = "10")]
+        drain_timeout: u64,
+        /// Sleep between each service this many milliseconds
+        #[structopt(long = "sleep", default_value = "0")]
+        sleep: u64,
+        /// Only print the services that would be deleted
+        #[structopt(long = "dry-run")]
+        dry_run: bool,
+        /// Delete without asking for the cluster name to be typed
+        #[structopt(long = "yes")]
+        yes: bool,
+    },
+    /// Print a manifest for `apply` of a cluster's services as they are
+    #[structopt(name = "dump-manifest")]
+    DumpManifest {
+        /// The cluster name
+        cluster: String,
+        /// The AWS region
+        region: Region,
+        /// The format of the manifest
+        #[structopt(
+            long = "format",
+            default_value = "json",
+            raw(
+                possible_values = "&InputFormat::variants()",
+                case_insensitive = "true"
+            )
+        )]
+        format: InputFormat,
+        /// Refer to task definitions as `family:revision` instead of including them
+        #[structopt(long = "references")]
+        references: bool,
+        /// Include each service's current desired count. Left out by default, so applying the
+        /// manifest doesn't undo auto scaling
+        #[structopt(long = "desired-counts")]
+        desired_counts: bool,
     },
 }
 
+/// The clusters and regions a command fans out over
+#[derive(Debug, StructOpt)]
+pub struct Targets {
+    /// The cluster names, comma separated. Use `*` in a name to match clusters, e.g. `prod-*`,
+    /// or `*` alone for every cluster in the regions
+    pub cluster: ClusterList,
+    /// The AWS regions, comma separated
+    pub region: RegionList,
+}
+
+#[derive(Debug, Clone)]
+pub struct ClusterList(pub Vec<String>);
+
+impl FromStr for ClusterList {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let clusters = s
+            .split(',')
+            .map(|c| c.trim())
+            .filter(|c| !c.is_empty())
+            .map(String::from)
+            .collect::<Vec<String>>();
+
+        if clusters.is_empty() {
+            bail!("No cluster given");
+        }
+
+        Ok(ClusterList(clusters))
+    }
+}
+
+impl fmt::Display for ClusterList {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0.join(","))
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RegionList(pub Vec<Region>);
+
+impl FromStr for RegionList {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let regions = s
+            .split(',')
+            .map(|r| r.trim())
+            .filter(|r| !r.is_empty())
+            .map(|r| Region::from_str(r).map_err(Error::from))
+            .collect::<Result<Vec<Region>, Error>>()?;
+
+        if regions.is_empty() {
+            bail!("No region given");
+        }
+
+        Ok(RegionList(regions))
+    }
+}
+
 arg_enum! {
     #[derive(Debug)]
     pub enum ServiceProperty {
@@ -117,30 +523,342 @@ arg_enum! {
 
 #[derive(Debug, StructOpt, Clone)]
 pub enum ServiceModification {
-    #[structopt(name = "desired-count")]
+    #[structopt(
+        name = "desired-count",
+        raw(setting = "structopt::clap::AppSettings::AllowNegativeNumbers")
+    )]
     DesiredCount {
-        /// Either an integer to set desired count for all services,
-        /// or a path to a JSON file containing a service name -> desired count mapping
+        /// Either an integer to set desired count for all services, a change relative to each
+        /// service's current count (`+2`, `-1`, `*0.5` or `50%`), or a path to a file
+        /// (or `-` for stdin) containing a service name -> desired count mapping
+        #[structopt(raw(allow_hyphen_values = "true"))]
         count: DesiredCountOptions,
+        /// Never set a desired count below this
+        #[structopt(long = "min")]
+        min: Option<i64>,
+        /// Never set a desired count above this
+        #[structopt(long = "max")]
+        max: Option<i64>,
+    },
+    /// Register a task definition revision with a container's image replaced and deploy it
+    #[structopt(name = "image")]
+    Image {
+        /// Either an image for all services, or just a `:tag` to keep each service's repository
+        image: Option<String>,
+        /// The container whose image to replace, needed when a task definition has more than one
+        #[structopt(long = "container")]
+        container: Option<String>,
+        /// A path to a file (or `-` for stdin) containing a service name -> image (or `:tag`)
+        /// mapping, used instead of the image argument
+        #[structopt(long = "map")]
+        map: Option<ServiceMap<String>>,
+    },
+    /// Register a task definition revision with a container's environment edited and deploy it
+    #[structopt(name = "environment")]
+    Environment {
+        /// The container whose environment to edit, needed when a task definition has more
+        /// than one
+        #[structopt(long = "container")]
+        container: Option<String>,
+        /// Set a variable, as NAME=VALUE (can be repeated)
+        #[structopt(long = "set", raw(number_of_values = "1"))]
+        set: Vec<Assignment>,
+        /// Remove a variable (can be repeated)
+        #[structopt(long = "remove", raw(number_of_values = "1"))]
+        remove: Vec<String>,
+        /// Rename a variable, as OLD=NEW (can be repeated)
+        #[structopt(long = "rename", raw(number_of_values = "1"))]
+        rename: Vec<Assignment>,
+    },
+    /// Register a task definition revision with different CPU and memory and deploy it
+    #[structopt(name = "resources")]
+    Resources {
+        /// The container to resize, needed for container-level changes when a task definition
+        /// has more than one
+        #[structopt(long = "container")]
+        container: Option<String>,
+        #[structopt(flatten)]
+        changes: ResourceChanges,
+        /// A path to a file (or `-` for stdin) containing a service name -> changes mapping,
+        /// used instead of the flags, e.g. {"api": {"task_memory": 2048, "cpu": "150%"}}
+        #[structopt(long = "map")]
+        map: Option<ServiceMap<ResourceChanges>>,
     },
 }
 
 #[derive(Debug, Clone)]
 pub enum DesiredCountOptions {
     Number(i64),
-    Map(HashMap<String, i64>),
+    Relative(Adjustment),
+    Map(ServiceMap<i64>),
+}
+
+/// A change to a service's current desired count
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Adjustment {
+    Add(i64),
+    /// Scale by a factor, rounding to the nearest count
+    Multiply(f64),
+}
+
+impl Adjustment {
+    pub fn apply(&self, current: i64) -> i64 {
+        let count = match *self {
+            Adjustment::Add(n) => current + n,
+            Adjustment::Multiply(factor) => (current as f64 * factor).round() as i64,
+        };
+
+        count.max(0)
+    }
+}
+
+/// Parses the number in a factor or percentage, which can't scale a count below zero
+fn scale_factor(number: &str, s: &str) -> Result<f64, Error> {
+    match number.parse::<f64>() {
+        Ok(factor) if factor.is_finite() && factor >= 0.0 => Ok(factor),
+        _ => bail!("Expected a factor or percentage of at least 0, got {:?}", s),
+    }
 }
 
 impl FromStr for DesiredCountOptions {
     type Err = Error;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        if let Ok(count) = s.parse::<i64>() {
+        if s == "-" {
+            Ok(DesiredCountOptions::Map(ServiceMap::from_str(s)?))
+        } else if s.starts_with('+') || s.starts_with('-') {
+            Ok(DesiredCountOptions::Relative(Adjustment::Add(
+                s.parse::<i64>()?,
+            )))
+        } else if let Some(factor) = s.strip_prefix('*') {
+            Ok(DesiredCountOptions::Relative(Adjustment::Multiply(
+                scale_factor(factor, s)?,
+            )))
+        } else if let Some(percentage) = s.strip_suffix('%') {
+            Ok(DesiredCountOptions::Relative(Adjustment::Multiply(
+                scale_factor(percentage, s)? / 100.0,
+            )))
+        } else if let Ok(count) = s.parse::<i64>() {
             Ok(DesiredCountOptions::Number(count))
         } else {
-            Ok(DesiredCountOptions::Map(serde_json::from_reader(
-                File::open(s)?,
-            )?))
+            Ok(DesiredCountOptions::Map(ServiceMap::from_str(s)?))
+        }
+    }
+}
+
+/// A `NAME=VALUE` pair
+#[derive(Debug, Clone)]
+pub struct Assignment {
+    pub name: String,
+    pub value: String,
+}
+
+impl FromStr for Assignment {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.find('=') {
+            Some(i) if i > 0 => Ok(Assignment {
+                name: s[..i].to_string(),
+                value: s[i + 1..].to_string(),
+            }),
+            _ => bail!("Expected NAME=VALUE, got {:?}", s),
         }
     }
 }
+
+impl ServiceModification {
+    /// The service names of the mapping file the modification was given, if any
+    pub fn mapped_services(&self) -> Option<Vec<&String>> {
+        match *self {
+            ServiceModification::DesiredCount {
+                count: DesiredCountOptions::Map(ref map),
+                ..
+            } => Some(map.service_names()),
+            ServiceModification::Image {
+                map: Some(ref map), ..
+            } => Some(map.service_names()),
+            ServiceModification::Resources {
+                map: Some(ref map), ..
+            } => Some(map.service_names()),
+            _ => None,
+        }
+    }
+
+    /// Checks the options that depend on each other
+    pub fn validate(&self) -> Result<(), Error> {
+        if let ServiceModification::DesiredCount { min, max, .. } = *self {
+            if min.is_some_and(|min| min < 0) || max.is_some_and(|max| max < 0) {
+                bail!("--min and --max can't be negative");
+            }
+            if let (Some(min), Some(max)) = (min, max) {
+                if min > max {
+                    bail!("--min {} is above --max {}", min, max);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Parses the mapping file the modification was given, if any, and picks out the entries
+    /// for one cluster
+    pub fn resolve_map(
+        &mut self,
+        format: Option<InputFormat>,
+        region: &str,
+        cluster: &str,
+    ) -> Result<(), Error> {
+        match *self {
+            ServiceModification::DesiredCount {
+                count: DesiredCountOptions::Map(ref mut map),
+                ..
+            } => map.resolve(format, region, cluster),
+            ServiceModification::Image {
+                map: Some(ref mut map),
+                ..
+            } => map.resolve(format, region, cluster),
+            ServiceModification::Resources {
+                map: Some(ref mut map),
+                ..
+            } => map.resolve(format, region, cluster),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// A new value for a resource: either absolute, or a percentage of the current value
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(try_from = "serde_json::Value")]
+pub enum Amount {
+    Absolute(i64),
+    Percentage(f64),
+}
+
+impl Amount {
+    /// The new value, or `None` when scaling a value that isn't set
+    pub fn resolve(&self, current: Option<i64>) -> Option<i64> {
+        match *self {
+            Amount::Absolute(value) => Some(value),
+            Amount::Percentage(percentage) => {
+                current.map(|current| (current as f64 * percentage / 100.0).round() as i64)
+            }
+        }
+    }
+}
+
+impl FromStr for Amount {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        if let Some(percentage) = s.strip_suffix('%') {
+            match percentage.trim().parse::<f64>() {
+                Ok(percentage) if percentage > 0.0 && percentage.is_finite() => {
+                    Ok(Amount::Percentage(percentage))
+                }
+                _ => bail!("Expected a positive percentage, got {:?}", s),
+            }
+        } else {
+            match s.parse::<i64>() {
+                Ok(value) if value > 0 => Ok(Amount::Absolute(value)),
+                _ => bail!("Expected a positive number or a percentage, got {:?}", s),
+            }
+        }
+    }
+}
+
+impl TryFrom<serde_json::Value> for Amount {
+    type Error = Error;
+
+    fn try_from(value: serde_json::Value) -> Result<Self, Self::Error> {
+        match value {
+            serde_json::Value::String(s) => Amount::from_str(&s),
+            serde_json::Value::Number(n) => Amount::from_str(&n.to_string()),
+            _ => bail!("Expected a number or a percentage, got {}", value),
+        }
+    }
+}
+
+/// Task- and container-level CPU and memory changes
+#[derive(Debug, Clone, Default, StructOpt, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ResourceChanges {
+    /// The task's CPU units, or a percentage of the current value like `150%`
+    #[structopt(long = "task-cpu")]
+    pub task_cpu: Option<Amount>,
+    /// The task's memory in MiB, or a percentage of the current value
+    #[structopt(long = "task-memory")]
+    pub task_memory: Option<Amount>,
+    /// The container's CPU units, or a percentage of the current value
+    #[structopt(long = "cpu")]
+    pub cpu: Option<Amount>,
+    /// The container's hard memory limit in MiB, or a percentage of the current value
+    #[structopt(long = "memory")]
+    pub memory: Option<Amount>,
+    /// The container's soft memory limit in MiB, or a percentage of the current value
+    #[structopt(long = "memory-reservation")]
+    pub memory_reservation: Option<Amount>,
+}
+
+impl ResourceChanges {
+    pub fn is_empty(&self) -> bool {
+        self.task_cpu.is_none()
+            && self.task_memory.is_none()
+            && self.cpu.is_none()
+            && self.memory.is_none()
+            && self.memory_reservation.is_none()
+    }
+
+    pub fn changes_container(&self) -> bool {
+        self.cpu.is_some() || self.memory.is_some() || self.memory_reservation.is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn adjustment(s: &str) -> Adjustment {
+        match DesiredCountOptions::from_str(s).unwrap() {
+            DesiredCountOptions::Relative(adjustment) => adjustment,
+            other => panic!("Expected an adjustment, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn desired_count_adjustments() {
+        assert_eq!(adjustment("+2").apply(3), 5);
+        assert_eq!(adjustment("-5").apply(3), 0);
+        assert_eq!(adjustment("*0.5").apply(3), 2);
+        assert_eq!(adjustment("150%").apply(4), 6);
+        assert_eq!(adjustment("*0").apply(4), 0);
+
+        match DesiredCountOptions::from_str("3").unwrap() {
+            DesiredCountOptions::Number(3) => {}
+            other => panic!("Expected a number, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn invalid_desired_count_adjustments() {
+        for s in &["*nan", "*inf", "*-1", "-50%", "inf%", "NaN%", "*"] {
+            assert!(DesiredCountOptions::from_str(s).is_err(), "{}", s);
+        }
+    }
+
+    #[test]
+    fn desired_count_bounds() {
+        let modification = |min, max| ServiceModification::DesiredCount {
+            count: DesiredCountOptions::Number(1),
+            min,
+            max,
+        };
+
+        assert!(modification(Some(1), Some(3)).validate().is_ok());
+        assert!(modification(Some(2), Some(2)).validate().is_ok());
+        assert!(modification(None, Some(0)).validate().is_ok());
+        assert!(modification(Some(3), Some(1)).validate().is_err());
+        assert!(modification(Some(-1), None).validate().is_err());
+    }
+}
@@ -0,0 +1,93 @@
+use failure::Error;
+use rusoto_ecs::Service;
+
+use super::{AuditCheck, AuditContext, Finding, Severity};
+use helpers;
+
+/// Service event messages that point at a rollout that can't make progress
+const FAILURE_EVENTS: &[&str] = &[
+    "was unable to place a task",
+    "failed container health checks",
+];
+
+pub struct Deployments {
+    pub timeout_minutes: u64,
+    pub repeated_event_count: usize,
+}
+
+impl AuditCheck for Deployments {
+    fn id(&self) -> &'static str {
+        "deployments"
+    }
+
+    fn severity(&self) -> Severity {
+        Severity::High
+    }
+
+    fn description(&self) -> &'static str {
+        "Rollouts finish within the deployment timeout without repeated placement or health check failures"
+    }
+
+    fn check(&self, _ctx: &AuditContext, service: &Service) -> Result<Vec<Finding>, Error> {
+        let timeout = self.timeout_minutes as f64;
+        let mut findings = Vec::new();
+
+        let deployments = service.deployments.clone().unwrap_or_default();
+        let primary = deployments
+            .iter()
+            .find(|d| d.status.as_ref().is_some_and(|s| s == "PRIMARY"));
+
+        if let Some(primary) = primary {
+            let task_definition = primary
+                .task_definition
+                .clone()
+                .unwrap_or_else(|| String::from("<unknown>"));
+            let age = primary.created_at.map_or(0.0, helpers::minutes_since);
+
+            if deployments.len() > 1 && age > timeout {
+                findings.push(self.finding(format!(
+                    "{} deployments have been active for {:.0} minutes while rolling out {}",
+                    deployments.len(),
+                    age,
+                    task_definition
+                )));
+            }
+
+            let running_count = primary.running_count.unwrap_or(0);
+            let desired_count = primary.desired_count.unwrap_or(0);
+            if running_count < desired_count && age > timeout {
+                findings.push(self.finding(format!(
+                    "Primary deployment of {} has {} of {} tasks running after {:.0} minutes",
+                    task_definition, running_count, desired_count, age
+                )));
+            }
+        }
+
+        let events = service.events.clone().unwrap_or_default();
+        for pattern in FAILURE_EVENTS {
+            let matching = events
+                .iter()
+                .filter(|e| {
+                    e.created_at
+                        .is_some_and(|t| helpers::minutes_since(t) <= timeout)
+                })
+                .filter_map(|e| e.message.as_ref())
+                .filter(|m| m.contains(pattern))
+                .collect::<Vec<&String>>();
+
+            if let Some(latest) = matching.first() {
+                if matching.len() >= self.repeated_event_count {
+                    findings.push(self.finding(format!(
+                        "{} {:?} events in the last {} minutes, most recently: {}",
+                        matching.len(),
+                        pattern,
+                        self.timeout_minutes,
+                        latest
+                    )));
+                }
+            }
+        }
+
+        Ok(findings)
+    }
+}
@@ -0,0 +1,43 @@
+use failure::Error;
+use rusoto_ecs::Service;
+
+use super::{AuditCheck, AuditContext, Finding, Severity};
+
+pub struct EcrImages;
+
+impl AuditCheck for EcrImages {
+    fn id(&self) -> &'static str {
+        "ecr-images"
+    }
+
+    fn severity(&self) -> Severity {
+        Severity::High
+    }
+
+    fn description(&self) -> &'static str {
+        "Every container image in the task definition exists in ECR"
+    }
+
+    fn check(&self, ctx: &AuditContext, service: &Service) -> Result<Vec<Finding>, Error> {
+        Ok(ctx
+            .container_images(service)?
+            .iter()
+            .filter_map(|container_image| {
+                container_image.error_summary().map(|error| {
+                    self.finding(format!(
+                        "Container {} uses {}, but repository {:?} {} in registry {} could not be described: {}",
+                        container_image.container_name,
+                        container_image.image,
+                        container_image.image.repository,
+                        match container_image.image.digest {
+                            Some(ref digest) => format!("digest {:?}", digest),
+                            None => format!("tag {:?}", container_image.image.tag_or_latest()),
+                        },
+                        container_image.image.registry.clone().unwrap_or_default(),
+                        error
+                    ))
+                })
+            })
+            .collect())
+    }
+}
@@ -0,0 +1,102 @@
+use failure::Error;
+use rusoto_ecs::Service;
+
+use super::{AuditCheck, AuditContext, Finding, Severity};
+use helpers;
+
+pub struct ImageAge {
+    pub max_age_days: u64,
+}
+
+impl AuditCheck for ImageAge {
+    fn id(&self) -> &'static str {
+        "image-age"
+    }
+
+    fn severity(&self) -> Severity {
+        Severity::Medium
+    }
+
+    fn description(&self) -> &'static str {
+        "No running ECR image was pushed longer ago than the maximum image age"
+    }
+
+    fn check(&self, ctx: &AuditContext, service: &Service) -> Result<Vec<Finding>, Error> {
+        Ok(ctx
+            .container_images(service)?
+            .iter()
+            .filter_map(|container_image| {
+                let image_detail = container_image.image_detail.as_ref().ok()?;
+                let age_days = helpers::minutes_since(image_detail.image_pushed_at?) / 60.0 / 24.0;
+
+                if age_days > self.max_age_days as f64 {
+                    Some(self.finding(format!(
+                        "Container {} runs {} ({}), pushed {:.0} days ago",
+                        container_image.container_name,
+                        container_image.image,
+                        image_detail.image_digest.clone().unwrap_or_default(),
+                        age_days
+                    )))
+                } else {
+                    None
+                }
+            })
+            .collect())
+    }
+}
+
+pub struct MovedTags;
+
+impl AuditCheck for MovedTags {
+    fn id(&self) -> &'static str {
+        "moved-tags"
+    }
+
+    fn severity(&self) -> Severity {
+        Severity::Medium
+    }
+
+    fn description(&self) -> &'static str {
+        "No image tag was pushed again after the service's primary deployment started (a heuristic: running task digests aren't available)"
+    }
+
+    fn check(&self, ctx: &AuditContext, service: &Service) -> Result<Vec<Finding>, Error> {
+        // This is a heuristic. The digest each running container resolved its tag to would
+        // say for sure, but rusoto_ecs 0.34 doesn't return it, so this assumes tasks pulled
+        // their tags when the primary deployment started them, and that a tag pushed after
+        // that point refers to a different digest than the one that is running. Tasks started
+        // later, e.g. by scaling out, may already run the newer push
+        let deployed_at = match service
+            .deployments
+            .iter()
+            .flat_map(|ds| ds.iter())
+            .find(|d| d.status.as_ref().is_some_and(|s| s == "PRIMARY"))
+            .and_then(|d| d.created_at)
+        {
+            Some(deployed_at) => deployed_at,
+            None => return Ok(Vec::new()),
+        };
+
+        Ok(ctx
+            .container_images(service)?
+            .iter()
+            .filter(|container_image| container_image.image.digest.is_none())
+            .filter_map(|container_image| {
+                let image_detail = container_image.image_detail.as_ref().ok()?;
+                let pushed_at = image_detail.image_pushed_at?;
+
+                if pushed_at > deployed_at {
+                    Some(self.finding(format!(
+                        "Container {} uses {}, which moved to {} {:.0} minutes after the service was deployed",
+                        container_image.container_name,
+                        container_image.image,
+                        image_detail.image_digest.clone().unwrap_or_default(),
+                        (pushed_at - deployed_at) / 60.0
+                    )))
+                } else {
+                    None
+                }
+            })
+            .collect())
+    }
+}
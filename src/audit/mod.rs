@@ -0,0 +1,366 @@
+use failure::Error;
+use rusoto_core::Region;
+use rusoto_ecr::EcrClient;
+use rusoto_ecs::{EcsClient, Service, TaskDefinition};
+use rusoto_elbv2::ElbClient;
+use serde_json;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::File;
+use std::rc::Rc;
+use std::str::FromStr;
+
+use helpers;
+use images::ImageReference;
+use services::{self, ContainerImage};
+use task_definitions;
+
+mod deployments;
+mod ecr_images;
+mod image_age;
+mod remediation;
+mod report;
+mod running_count;
+mod suppressions;
+mod target_groups;
+mod target_health;
+mod task_definition;
+
+pub use self::deployments::Deployments;
+pub use self::ecr_images::EcrImages;
+pub use self::image_age::{ImageAge, MovedTags};
+pub use self::remediation::{apply as apply_remediation, plan as plan_remediation};
+pub use self::report::{has_findings_at, render, OutputFormat, ServiceReport};
+pub use self::running_count::RunningCount;
+pub use self::suppressions::{SuppressedFinding, Suppressions};
+pub use self::target_groups::TargetGroups;
+pub use self::target_health::TargetHealth;
+pub use self::task_definition::{
+    HealthChecks, ImageTags, LogConfiguration, MemoryLimits, PlaintextSecrets, Privileged,
+};
+
+arg_enum! {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+    pub enum Severity {
+        Info,
+        Low,
+        Medium,
+        High,
+        Critical,
+    }
+}
+
+/// A single problem found by an `AuditCheck`
+#[derive(Debug, Clone, Serialize)]
+pub struct Finding {
+    pub check: &'static str,
+    pub severity: Severity,
+    pub detail: String,
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "[{}] {}: {}", self.severity, self.check, self.detail)
+    }
+}
+
+/// The AWS clients available to checks while auditing a service
+pub struct AuditContext {
+    pub ecs_client: EcsClient,
+    pub elb_client: ElbClient,
+    profile: Option<String>,
+    region: Region,
+    ecr_clients: RefCell<HashMap<String, Rc<EcrClient>>>,
+    task_definitions: RefCell<HashMap<String, TaskDefinition>>,
+    container_images: RefCell<HashMap<String, Rc<Vec<ContainerImage>>>>,
+}
+
+impl AuditContext {
+    pub fn new(profile: Option<String>, region: Region) -> Result<Self, Error> {
+        Ok(AuditContext {
+            ecs_client: helpers::ecs_client(profile.clone(), region.clone())?,
+            elb_client: helpers::elb_client(profile.clone(), region.clone())?,
+            profile,
+            region,
+            ecr_clients: RefCell::new(HashMap::new()),
+            task_definitions: RefCell::new(HashMap::new()),
+            container_images: RefCell::new(HashMap::new()),
+        })
+    }
+
+    /// An ECR client for the given region, which may differ from the audited cluster's
+    pub fn ecr_client(&self, region: &str) -> Result<Rc<EcrClient>, Error> {
+        if let Some(ecr_client) = self.ecr_clients.borrow().get(region) {
+            return Ok(ecr_client.clone());
+        }
+
+        let client_region = if region == self.region.name() {
+            self.region.clone()
+        } else {
+            Region::from_str(region)?
+        };
+        let ecr_client = Rc::new(helpers::ecr_client(self.profile.clone(), client_region)?);
+        self.ecr_clients
+            .borrow_mut()
+            .insert(region.to_string(), ecr_client.clone());
+
+        Ok(ecr_client)
+    }
+
+    /// The service's task definition, described once and shared between checks
+    pub fn task_definition(&self, service: &Service) -> Result<Option<TaskDefinition>, Error> {
+        match service.task_definition {
+            Some(ref arn) => Ok(Some(self.describe_task_definition(arn)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn describe_task_definition(&self, arn: &str) -> Result<TaskDefinition, Error> {
+        if let Some(task_definition) = self.task_definitions.borrow().get(arn) {
+            return Ok(task_definition.clone());
+        }
+
+        let task_definition =
+            task_definitions::describe_task_definition(&self.ecs_client, arn.to_string())?;
+        self.task_definitions
+            .borrow_mut()
+            .insert(arn.to_string(), task_definition.clone());
+
+        Ok(task_definition)
+    }
+
+    /// Looks up every ECR-hosted container image of the service in the registry it is pulled
+    /// from. Images from other registries, such as Docker Hub, are skipped
+    pub fn container_images(&self, service: &Service) -> Result<Rc<Vec<ContainerImage>>, Error> {
+        match service.task_definition {
+            Some(ref arn) => self.task_definition_images(arn),
+            None => Ok(Rc::new(Vec::new())),
+        }
+    }
+
+    /// Like `container_images`, for any revision of a task definition
+    pub fn task_definition_images(&self, arn: &str) -> Result<Rc<Vec<ContainerImage>>, Error> {
+        let task_definition = self.describe_task_definition(arn)?;
+        let arn = task_definition
+            .task_definition_arn
+            .clone()
+            .unwrap_or_else(|| arn.to_string());
+
+        if let Some(images) = self.container_images.borrow().get(&arn) {
+            return Ok(images.clone());
+        }
+
+        let mut images = Vec::new();
+
+        for container_definition in task_definition.container_definitions.unwrap_or_default() {
+            let container_name = container_definition.name.unwrap_or_default();
+            let image = match container_definition.image {
+                Some(image) => image,
+                None => continue,
+            };
+
+            let image = match ImageReference::from_str(&image) {
+                Ok(image) => image,
+                Err(e) => {
+                    warn!("Skipping container {}: {}", container_name, e);
+                    continue;
+                }
+            };
+            let registry = match image.ecr_registry() {
+                Some(registry) => registry,
+                None => {
+                    debug!("Skipping {}, which is not hosted in ECR", image);
+                    continue;
+                }
+            };
+
+            let image_detail = services::describe_ecr_image(
+                &*self.ecr_client(&registry.region)?,
+                &image,
+                &registry,
+            );
+
+            images.push(ContainerImage {
+                container_name,
+                image,
+                image_detail,
+            });
+        }
+
+        let images = Rc::new(images);
+        self.container_images
+            .borrow_mut()
+            .insert(arn, images.clone());
+
+        Ok(images)
+    }
+}
+
+pub trait AuditCheck {
+    /// Short, stable identifier used to enable or disable the check
+    fn id(&self) -> &'static str;
+
+    /// The severity of findings reported by this check
+    fn severity(&self) -> Severity;
+
+    /// What the check verifies
+    fn description(&self) -> &'static str;
+
+    /// Inspects the service, returning one finding per problem
+    fn check(&self, ctx: &AuditContext, service: &Service) -> Result<Vec<Finding>, Error>;
+
+    fn finding(&self, detail: String) -> Finding {
+        Finding {
+            check: self.id(),
+            severity: self.severity(),
+            detail,
+        }
+    }
+}
+
+/// Every check this tool knows about
+pub fn all_checks(config: &AuditConfig) -> Vec<Box<dyn AuditCheck>> {
+    vec![
+        Box::new(EcrImages),
+        Box::new(ImageAge {
+            max_age_days: config.max_image_age_days,
+        }),
+        Box::new(MovedTags),
+        Box::new(TargetGroups),
+        Box::new(TargetHealth),
+        Box::new(RunningCount),
+        Box::new(Deployments {
+            timeout_minutes: config.deployment_timeout_minutes,
+            repeated_event_count: config.repeated_event_count,
+        }),
+        Box::new(MemoryLimits),
+        Box::new(LogConfiguration),
+        Box::new(ImageTags),
+        Box::new(Privileged),
+        Box::new(PlaintextSecrets),
+        Box::new(HealthChecks),
+    ]
+}
+
+/// Checks a service must pass before `sync` copies it to another cluster
+pub fn sync_checks() -> Vec<Box<dyn AuditCheck>> {
+    vec![
+        Box::new(EcrImages),
+        Box::new(TargetGroups),
+        Box::new(RunningCount),
+    ]
+}
+
+/// Which checks to run and how, either from a JSON file or from the command line
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct AuditConfig {
+    /// Only run these checks. All checks run when this is absent
+    pub enabled: Option<Vec<String>>,
+    /// Never run these checks
+    pub disabled: Vec<String>,
+    /// How long a rollout may take before it is considered stuck
+    pub deployment_timeout_minutes: u64,
+    /// How many matching service events within the deployment timeout count as repeated
+    pub repeated_event_count: usize,
+    /// Running images pushed longer ago than this are reported
+    pub max_image_age_days: u64,
+}
+
+impl Default for AuditConfig {
+    fn default() -> Self {
+        AuditConfig {
+            enabled: None,
+            disabled: Vec::new(),
+            deployment_timeout_minutes: 30,
+            repeated_event_count: 3,
+            max_image_age_days: 90,
+        }
+    }
+}
+
+impl AuditConfig {
+    /// Applies `--check` and `--skip-check` on top of this config
+    pub fn with_overrides(mut self, enabled: Vec<String>, disabled: Vec<String>) -> Self {
+        if !enabled.is_empty() {
+            self.enabled = Some(enabled);
+        }
+        self.disabled.extend(disabled);
+        self
+    }
+}
+
+impl FromStr for AuditConfig {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let config: AuditConfig = serde_json::from_reader(File::open(s)?)?;
+
+        if config.repeated_event_count < 1 {
+            bail!("repeated_event_count in {} must be at least 1", s);
+        }
+
+        Ok(config)
+    }
+}
+
+pub fn select_checks(config: &AuditConfig) -> Result<Vec<Box<dyn AuditCheck>>, Error> {
+    let checks = all_checks(config);
+
+    for id in config
+        .enabled
+        .iter()
+        .flat_map(|ids| ids.iter())
+        .chain(config.disabled.iter())
+    {
+        if !checks.iter().any(|c| c.id() == id) {
+            bail!(
+                "Unknown audit check {:?}, expected one of:\n{}",
+                id,
+                checks
+                    .iter()
+                    .map(|c| format!("  {} - {}", c.id(), c.description()))
+                    .collect::<Vec<String>>()
+                    .join("\n")
+            );
+        }
+    }
+
+    Ok(checks
+        .into_iter()
+        .filter(|c| {
+            config
+                .enabled
+                .as_ref()
+                .is_none_or(|ids| ids.iter().any(|id| id == c.id()))
+        })
+        .filter(|c| !config.disabled.iter().any(|id| id == c.id()))
+        .collect())
+}
+
+pub fn audit_service(
+    ctx: &AuditContext,
+    checks: &[Box<dyn AuditCheck>],
+    suppressions: &Suppressions,
+    cluster: &str,
+    service: &Service,
+) -> Result<ServiceReport, Error> {
+    let service_name = services::service_name(service)?;
+    let mut findings = Vec::new();
+
+    for check in checks {
+        trace!("Running {} against {}", check.id(), service_name);
+        findings.append(&mut check.check(ctx, service)?);
+    }
+
+    let (findings, suppressed) = suppressions.apply(&service_name, findings);
+
+    Ok(ServiceReport {
+        region: ctx.region.name().to_string(),
+        cluster: cluster.to_string(),
+        service: service_name,
+        findings,
+        suppressed,
+    })
+}
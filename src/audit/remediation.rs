@@ -0,0 +1,250 @@
+use failure::Error;
+use rusoto_ecs::{Service, UpdateServiceRequest};
+use std::fmt;
+
+use super::{AuditContext, ServiceReport};
+use services::{self, ContainerImage};
+use task_definitions;
+
+/// A mechanical fix for a service's audit findings
+#[derive(Debug, Clone)]
+pub enum Remediation {
+    /// Replace the tasks of a service that is stuck below its desired count
+    ForceNewDeployment { cluster: String, service: String },
+    /// Go back to the revision before one that references a missing ECR image
+    RollBack {
+        cluster: String,
+        service: String,
+        from: String,
+        to: String,
+    },
+    /// Stop a service whose target groups no longer exist
+    ScaleToZero { cluster: String, service: String },
+}
+
+impl fmt::Display for Remediation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Remediation::ForceNewDeployment {
+                ref cluster,
+                ref service,
+            } => write!(f, "{}/{}: force a new deployment", cluster, service),
+            Remediation::RollBack {
+                ref cluster,
+                ref service,
+                ref from,
+                ref to,
+            } => write!(
+                f,
+                "{}/{}: roll back from {} to {}",
+                cluster, service, from, to
+            ),
+            Remediation::ScaleToZero {
+                ref cluster,
+                ref service,
+            } => write!(f, "{}/{}: scale to zero", cluster, service),
+        }
+    }
+}
+
+/// Picks the remediation for a service's findings, if there is a mechanical one.
+/// A service without target groups can't serve traffic however it is deployed,
+/// so that takes precedence over a missing image, which takes precedence over a low task count
+pub fn plan(
+    ctx: &AuditContext,
+    service: &Service,
+    report: &ServiceReport,
+) -> Result<Option<Remediation>, Error> {
+    let has_finding = |check: &str| report.findings.iter().any(|f| f.check == check);
+    let cluster = report.cluster.clone();
+    let service_name = report.service.clone();
+
+    // Only findings that say something doesn't exist are remediated. A finding caused by
+    // failing to look it up, such as AccessDenied, says nothing about the service
+    let target_group_missing = has_finding("target-groups")
+        && services::service_target_groups(&ctx.elb_client, service)?
+            .iter()
+            .filter_map(|(_, target_group)| target_group.as_ref().err())
+            .any(services::is_target_group_not_found);
+
+    if target_group_missing {
+        return Ok(Some(Remediation::ScaleToZero {
+            cluster,
+            service: service_name,
+        }));
+    }
+
+    let image_missing = has_finding("ecr-images")
+        && ctx
+            .container_images(service)?
+            .iter()
+            .any(ContainerImage::is_missing);
+
+    let missing_image = if image_missing {
+        rollback(ctx, service, &service_name)?
+    } else {
+        MissingImage::None
+    };
+
+    Ok(choose(
+        cluster,
+        service_name,
+        missing_image,
+        has_finding("running-count"),
+    ))
+}
+
+/// What can be done about a service's task definition referencing a missing image
+#[derive(Debug, Clone, PartialEq)]
+enum MissingImage {
+    None,
+    RollBack {
+        from: String,
+        to: String,
+    },
+    /// No earlier revision exists, or it references a missing image too
+    Unfixable,
+}
+
+/// Whether the service can go back to its previous revision, whose images must all exist
+fn rollback(
+    ctx: &AuditContext,
+    service: &Service,
+    service_name: &str,
+) -> Result<MissingImage, Error> {
+    let task_definition = match ctx.task_definition(service)? {
+        Some(task_definition) => task_definition,
+        None => return Ok(MissingImage::Unfixable),
+    };
+
+    let previous =
+        match task_definitions::previous_task_definition(&ctx.ecs_client, &task_definition)? {
+            Some(previous) => previous,
+            None => {
+                warn!(
+                    "{} references a missing image, but has no earlier revision to roll back to",
+                    service_name
+                );
+                return Ok(MissingImage::Unfixable);
+            }
+        };
+
+    let previous_images = ctx.task_definition_images(&previous)?;
+    if let Some(previous_image) = previous_images.iter().find(|ci| ci.image_detail.is_err()) {
+        warn!(
+            "{} references a missing image, but can't be rolled back to {} either, as container {} uses {}: {}",
+            service_name,
+            previous,
+            previous_image.container_name,
+            previous_image.image,
+            previous_image.error_summary().unwrap_or_default()
+        );
+        return Ok(MissingImage::Unfixable);
+    }
+
+    Ok(MissingImage::RollBack {
+        from: task_definition.task_definition_arn.unwrap_or_default(),
+        to: previous,
+    })
+}
+
+/// The remediation for what `plan` found. A missing image that can't be rolled back is left
+/// alone: forcing a new deployment would only start tasks that fail to pull it
+fn choose(
+    cluster: String,
+    service: String,
+    missing_image: MissingImage,
+    running_count_low: bool,
+) -> Option<Remediation> {
+    match missing_image {
+        MissingImage::RollBack { from, to } => Some(Remediation::RollBack {
+            cluster,
+            service,
+            from,
+            to,
+        }),
+        MissingImage::Unfixable => None,
+        MissingImage::None if running_count_low => {
+            Some(Remediation::ForceNewDeployment { cluster, service })
+        }
+        MissingImage::None => None,
+    }
+}
+
+pub fn apply(ctx: &AuditContext, remediation: &Remediation) -> Result<(), Error> {
+    let req = match *remediation {
+        Remediation::ForceNewDeployment {
+            ref cluster,
+            ref service,
+        } => UpdateServiceRequest {
+            force_new_deployment: Some(true),
+            ..services::update_service_template(cluster.clone(), service.clone())
+        },
+        Remediation::RollBack {
+            ref cluster,
+            ref service,
+            ref to,
+            ..
+        } => UpdateServiceRequest {
+            task_definition: Some(to.clone()),
+            ..services::update_service_template(cluster.clone(), service.clone())
+        },
+        Remediation::ScaleToZero {
+            ref cluster,
+            ref service,
+        } => UpdateServiceRequest {
+            desired_count: Some(0),
+            ..services::update_service_template(cluster.clone(), service.clone())
+        },
+    };
+
+    eprintln!("Applying {}", remediation);
+    services::send_update_service(&ctx.ecs_client, req, format!("Applying {}", remediation))?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn choose_for(missing_image: MissingImage, running_count_low: bool) -> Option<String> {
+        choose(
+            String::from("prod"),
+            String::from("api"),
+            missing_image,
+            running_count_low,
+        )
+        .map(|remediation| remediation.to_string())
+    }
+
+    fn rollback() -> MissingImage {
+        MissingImage::RollBack {
+            from: String::from("app:3"),
+            to: String::from("app:2"),
+        }
+    }
+
+    #[test]
+    fn remediations() {
+        assert_eq!(choose_for(MissingImage::None, false), None);
+        assert_eq!(
+            choose_for(MissingImage::None, true),
+            Some(String::from("prod/api: force a new deployment"))
+        );
+        assert_eq!(
+            choose_for(rollback(), false),
+            Some(String::from("prod/api: roll back from app:3 to app:2"))
+        );
+        assert_eq!(
+            choose_for(rollback(), true),
+            Some(String::from("prod/api: roll back from app:3 to app:2"))
+        );
+    }
+
+    #[test]
+    fn missing_images_without_a_rollback_are_left_alone() {
+        assert_eq!(choose_for(MissingImage::Unfixable, false), None);
+        assert_eq!(choose_for(MissingImage::Unfixable, true), None);
+    }
+}
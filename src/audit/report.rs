@@ -0,0 +1,398 @@
+use failure::Error;
+use serde_json::{self, Value};
+
+use super::{AuditCheck, Finding, Severity, SuppressedFinding};
+use helpers;
+
+arg_enum! {
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub enum OutputFormat {
+        Text,
+        Json,
+        Junit,
+        Sarif,
+        Markdown,
+    }
+}
+
+/// The findings for one audited service
+#[derive(Debug, Clone, Serialize)]
+pub struct ServiceReport {
+    pub region: String,
+    pub cluster: String,
+    pub service: String,
+    pub findings: Vec<Finding>,
+    /// Findings that matched a suppression, kept apart so they don't drown out new problems
+    pub suppressed: Vec<SuppressedFinding>,
+}
+
+impl ServiceReport {
+    /// The service name, qualified with its region and cluster when reporting on more than one
+    fn name(&self, fleet: bool) -> String {
+        if fleet {
+            format!("{}/{}/{}", self.region, self.cluster, self.service)
+        } else {
+            self.service.clone()
+        }
+    }
+}
+
+/// Whether any finding is at least as severe as `severity`
+pub fn has_findings_at(reports: &[ServiceReport], severity: Severity) -> bool {
+    reports
+        .iter()
+        .flat_map(|r| r.findings.iter())
+        .any(|f| f.severity >= severity)
+}
+
+pub fn render(
+    format: OutputFormat,
+    checks: &[Box<dyn AuditCheck>],
+    reports: &[ServiceReport],
+) -> Result<String, Error> {
+    match format {
+        OutputFormat::Text => Ok(text(reports)),
+        OutputFormat::Json => Ok(serde_json::to_string_pretty(reports)?),
+        OutputFormat::Junit => Ok(junit(reports)),
+        OutputFormat::Sarif => Ok(serde_json::to_string_pretty(&sarif(checks, reports))?),
+        OutputFormat::Markdown => Ok(markdown(reports)),
+    }
+}
+
+fn text(reports: &[ServiceReport]) -> String {
+    let fleet = helpers::is_fleet(
+        reports
+            .iter()
+            .map(|r| (r.region.as_str(), r.cluster.as_str())),
+    );
+    let mut text = reports
+        .iter()
+        .flat_map(|r| {
+            let name = r.name(fleet);
+            r.findings.iter().map(move |f| format!("{} {}\n", name, f))
+        })
+        .collect::<String>();
+
+    if reports.iter().any(|r| !r.suppressed.is_empty()) {
+        text.push_str("\nSuppressed:\n");
+        for report in reports {
+            for suppressed in &report.suppressed {
+                text.push_str(&format!(
+                    "{} {} ({})\n",
+                    report.name(fleet),
+                    suppressed.finding,
+                    suppression_note(suppressed)
+                ));
+            }
+        }
+    }
+
+    text
+}
+
+fn suppression_note(suppressed: &SuppressedFinding) -> String {
+    match (&suppressed.reason, &suppressed.expires) {
+        (Some(reason), Some(expires)) => format!("{}, until {}", reason, expires),
+        (Some(reason), None) => reason.clone(),
+        (None, Some(expires)) => format!("until {}", expires),
+        (None, None) => String::from("no reason given"),
+    }
+}
+
+fn junit(reports: &[ServiceReport]) -> String {
+    let failures = reports.iter().filter(|r| !r.findings.is_empty()).count();
+
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str(&format!(
+        "<testsuite name=\"ecs-manage audit\" tests=\"{}\" failures=\"{}\">\n",
+        reports.len(),
+        failures
+    ));
+
+    for report in reports {
+        xml.push_str(&format!(
+            "  <testcase classname=\"{}.{}\" name=\"{}\"",
+            xml_escape(&report.region),
+            xml_escape(&report.cluster),
+            xml_escape(&report.service)
+        ));
+
+        if report.findings.is_empty() && report.suppressed.is_empty() {
+            xml.push_str("/>\n");
+            continue;
+        }
+
+        xml.push_str(">\n");
+        if report.findings.is_empty() {
+            xml.push_str(&format!(
+                "    <skipped message=\"{} suppressed findings\"/>\n",
+                report.suppressed.len()
+            ));
+        }
+        for finding in &report.findings {
+            xml.push_str(&format!(
+                "    <failure type=\"{}\" message=\"{}\">{}</failure>\n",
+                xml_escape(finding.check),
+                xml_escape(&format!("[{}] {}", finding.severity, finding.detail)),
+                xml_escape(&finding.detail)
+            ));
+        }
+        if !report.suppressed.is_empty() {
+            xml.push_str("    <system-out>");
+            for suppressed in &report.suppressed {
+                xml.push_str(&xml_escape(&format!(
+                    "Suppressed {} ({})\n",
+                    suppressed.finding,
+                    suppression_note(suppressed)
+                )));
+            }
+            xml.push_str("</system-out>\n");
+        }
+        xml.push_str("  </testcase>\n");
+    }
+
+    xml.push_str("</testsuite>\n");
+    xml
+}
+
+fn xml_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+fn sarif_level(severity: Severity) -> &'static str {
+    match severity {
+        Severity::Critical | Severity::High => "error",
+        Severity::Medium => "warning",
+        Severity::Low | Severity::Info => "note",
+    }
+}
+
+fn sarif(checks: &[Box<dyn AuditCheck>], reports: &[ServiceReport]) -> Value {
+    let rules = checks
+        .iter()
+        .map(|c| {
+            json!({
+                "id": c.id(),
+                "shortDescription": { "text": c.description() },
+                "defaultConfiguration": { "level": sarif_level(c.severity()) },
+            })
+        })
+        .collect::<Vec<Value>>();
+
+    let mut results = Vec::new();
+    for report in reports {
+        for finding in &report.findings {
+            results.push(sarif_result(report, finding));
+        }
+        for suppressed in &report.suppressed {
+            let mut result = sarif_result(report, &suppressed.finding);
+            result["suppressions"] = json!([{
+                "kind": "external",
+                "justification": suppression_note(suppressed),
+            }]);
+            results.push(result);
+        }
+    }
+
+    json!({
+        "version": "2.1.0",
+        "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": crate_name!(),
+                    "version": crate_version!(),
+                    "rules": rules,
+                },
+            },
+            "results": results,
+        }],
+    })
+}
+
+fn sarif_result(report: &ServiceReport, finding: &Finding) -> Value {
+    json!({
+        "ruleId": finding.check,
+        "level": sarif_level(finding.severity),
+        "message": { "text": finding.detail },
+        "locations": [{
+            "logicalLocations": [{
+                "name": report.service,
+                "fullyQualifiedName": report.name(true),
+                "kind": "service",
+            }],
+        }],
+    })
+}
+
+fn markdown(reports: &[ServiceReport]) -> String {
+    let fleet = helpers::is_fleet(
+        reports
+            .iter()
+            .map(|r| (r.region.as_str(), r.cluster.as_str())),
+    );
+    let mut md = String::from("| Service | Severity | Check | Detail |\n");
+    md.push_str("|---|---|---|---|\n");
+
+    for report in reports {
+        for finding in &report.findings {
+            md.push_str(&format!(
+                "| {} | {} | {} | {} |\n",
+                markdown_escape(&report.name(fleet)),
+                finding.severity,
+                finding.check,
+                markdown_escape(&finding.detail)
+            ));
+        }
+    }
+
+    if reports.iter().any(|r| !r.suppressed.is_empty()) {
+        md.push_str("\n### Suppressed\n\n");
+        md.push_str("| Service | Severity | Check | Detail | Reason |\n");
+        md.push_str("|---|---|---|---|---|\n");
+
+        for report in reports {
+            for suppressed in &report.suppressed {
+                md.push_str(&format!(
+                    "| {} | {} | {} | {} | {} |\n",
+                    markdown_escape(&report.name(fleet)),
+                    suppressed.finding.severity,
+                    suppressed.finding.check,
+                    markdown_escape(&suppressed.finding.detail),
+                    markdown_escape(&suppression_note(suppressed))
+                ));
+            }
+        }
+    }
+
+    md
+}
+
+fn markdown_escape(s: &str) -> String {
+    s.replace('|', "\\|").replace('\n', " ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use audit::MemoryLimits;
+
+    fn finding(severity: Severity, detail: &str) -> Finding {
+        Finding {
+            check: "memory-limits",
+            severity,
+            detail: detail.to_string(),
+        }
+    }
+
+    fn report(findings: Vec<Finding>, suppressed: Vec<Finding>) -> ServiceReport {
+        ServiceReport {
+            region: String::from("us-east-1"),
+            cluster: String::from("prod"),
+            service: String::from("api"),
+            findings,
+            suppressed: suppressed
+                .into_iter()
+                .map(|finding| SuppressedFinding {
+                    finding,
+                    reason: Some(String::from("accepted")),
+                    expires: None,
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn escapes_xml() {
+        assert_eq!(
+            xml_escape(r#"<a href="x">Tom & 'Jerry'</a>"#),
+            "&lt;a href=&quot;x&quot;&gt;Tom &amp; &apos;Jerry&apos;&lt;/a&gt;"
+        );
+        assert_eq!(xml_escape("&amp;"), "&amp;amp;");
+    }
+
+    #[test]
+    fn severity_thresholds() {
+        let reports = vec![
+            report(vec![finding(Severity::Medium, "a")], Vec::new()),
+            report(Vec::new(), Vec::new()),
+        ];
+
+        assert!(has_findings_at(&reports, Severity::Info));
+        assert!(has_findings_at(&reports, Severity::Medium));
+        assert!(!has_findings_at(&reports, Severity::High));
+        assert!(!has_findings_at(&[], Severity::Info));
+    }
+
+    #[test]
+    fn suppressed_findings_dont_fail() {
+        let reports = vec![report(Vec::new(), vec![finding(Severity::Critical, "a")])];
+
+        assert!(!has_findings_at(&reports, Severity::Info));
+
+        let xml = junit(&reports);
+        assert!(xml.contains("tests=\"1\" failures=\"0\""), "{}", xml);
+        assert!(xml.contains("<skipped message=\"1 suppressed findings\"/>"));
+        assert!(!xml.contains("<failure"));
+    }
+
+    #[test]
+    fn junit_failures() {
+        let reports = vec![
+            report(
+                vec![finding(Severity::High, "uses <latest> & more")],
+                Vec::new(),
+            ),
+            report(Vec::new(), Vec::new()),
+        ];
+
+        let xml = junit(&reports);
+        assert!(xml.contains("tests=\"2\" failures=\"1\""), "{}", xml);
+        assert!(xml.contains(
+            "<failure type=\"memory-limits\" message=\"[High] uses &lt;latest&gt; &amp; more\">"
+        ));
+        assert!(xml.contains("<testcase classname=\"us-east-1.prod\" name=\"api\"/>"));
+    }
+
+    #[test]
+    fn sarif_marks_suppressions() {
+        let checks: Vec<Box<dyn AuditCheck>> = vec![Box::new(MemoryLimits)];
+        let reports = vec![report(
+            vec![finding(Severity::Low, "a")],
+            vec![finding(Severity::High, "b")],
+        )];
+
+        let sarif = sarif(&checks, &reports);
+        let results = sarif["runs"][0]["results"].as_array().unwrap();
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0]["level"], "note");
+        assert!(results[0].get("suppressions").is_none());
+        assert_eq!(results[1]["level"], "error");
+        assert_eq!(results[1]["suppressions"][0]["justification"], "accepted");
+        assert_eq!(
+            sarif["runs"][0]["tool"]["driver"]["rules"][0]["id"],
+            "memory-limits"
+        );
+    }
+
+    #[test]
+    fn markdown_escapes_cells() {
+        let reports = vec![report(
+            vec![finding(Severity::Low, "a | b\nc")],
+            vec![finding(Severity::Low, "d")],
+        )];
+
+        let md = markdown(&reports);
+        assert!(
+            md.contains("| api | Low | memory-limits | a \\| b c |\n"),
+            "{}",
+            md
+        );
+        assert!(md.contains("### Suppressed"));
+        assert!(md.contains("| api | Low | memory-limits | d | accepted |\n"));
+    }
+}
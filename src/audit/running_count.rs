@@ -0,0 +1,34 @@
+use failure::Error;
+use rusoto_ecs::Service;
+
+use super::{AuditCheck, AuditContext, Finding, Severity};
+
+pub struct RunningCount;
+
+impl AuditCheck for RunningCount {
+    fn id(&self) -> &'static str {
+        "running-count"
+    }
+
+    fn severity(&self) -> Severity {
+        Severity::Medium
+    }
+
+    fn description(&self) -> &'static str {
+        "The service is running at least as many tasks as desired"
+    }
+
+    fn check(&self, _ctx: &AuditContext, service: &Service) -> Result<Vec<Finding>, Error> {
+        let running_count = service.running_count.unwrap_or(0);
+        let desired_count = service.desired_count.unwrap_or(0);
+
+        if running_count < desired_count {
+            Ok(vec![self.finding(format!(
+                "Less than desired: {} of {} tasks running",
+                running_count, desired_count
+            ))])
+        } else {
+            Ok(Vec::new())
+        }
+    }
+}
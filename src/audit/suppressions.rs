@@ -0,0 +1,215 @@
+use failure::Error;
+use serde_json;
+use std::fs::File;
+use std::str::FromStr;
+
+use super::Finding;
+use helpers;
+
+/// An accepted finding that should not be reported as a problem
+#[derive(Debug, Clone, Deserialize)]
+pub struct Suppression {
+    /// Service name pattern, where `*` matches anything
+    pub service: String,
+    /// Check id pattern, where `*` matches anything
+    pub check: String,
+    /// The last day (`YYYY-MM-DD`) this suppression applies
+    pub expires: Option<String>,
+    pub reason: Option<String>,
+}
+
+/// A finding that matched a suppression
+#[derive(Debug, Clone, Serialize)]
+pub struct SuppressedFinding {
+    #[serde(flatten)]
+    pub finding: Finding,
+    pub reason: Option<String>,
+    pub expires: Option<String>,
+}
+
+/// A JSON file containing a list of suppressions
+#[derive(Debug, Clone, Default)]
+pub struct Suppressions(pub Vec<Suppression>);
+
+impl FromStr for Suppressions {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let suppressions: Vec<Suppression> = serde_json::from_reader(File::open(s)?)?;
+
+        for suppression in &suppressions {
+            if let Some(ref expires) = suppression.expires {
+                if !is_date(expires) {
+                    bail!(
+                        "Suppression of {} for {} expires on {:?}, which is not a YYYY-MM-DD date",
+                        suppression.check,
+                        suppression.service,
+                        expires
+                    );
+                }
+            }
+        }
+
+        Ok(Suppressions(suppressions))
+    }
+}
+
+/// Whether a string is a real `YYYY-MM-DD` date, which then compares correctly as a string
+fn is_date(s: &str) -> bool {
+    let shaped = s.len() == 10
+        && s.char_indices().all(|(i, c)| {
+            if i == 4 || i == 7 {
+                c == '-'
+            } else {
+                c.is_ascii_digit()
+            }
+        });
+    if !shaped {
+        return false;
+    }
+
+    let (year, month, day) = match (s[..4].parse::<u32>(), s[5..7].parse(), s[8..].parse()) {
+        (Ok(year), Ok(month), Ok(day)) => (year, month, day),
+        _ => return false,
+    };
+    let leap = year % 4 == 0 && (year % 100 != 0 || year % 400 == 0);
+    let days_in_month = match month {
+        1 | 3 | 5 | 7 | 8 | 10 | 12 => 31,
+        4 | 6 | 9 | 11 => 30,
+        2 if leap => 29,
+        2 => 28,
+        _ => return false,
+    };
+
+    (1..=days_in_month).contains(&day)
+}
+
+impl Suppressions {
+    /// The unexpired suppression covering a finding for the given service, if any
+    pub fn find(&self, service_name: &str, finding: &Finding) -> Option<&Suppression> {
+        let today = helpers::today();
+
+        self.0
+            .iter()
+            .filter(|s| helpers::glob_match(&s.service, service_name))
+            .filter(|s| helpers::glob_match(&s.check, finding.check))
+            .find(|s| match s.expires {
+                Some(ref expires) if **expires < *today => {
+                    warn!(
+                        "Suppression of {} for {} expired on {}",
+                        s.check, service_name, expires
+                    );
+                    false
+                }
+                _ => true,
+            })
+    }
+
+    /// Splits findings into those still reported and those that are suppressed
+    pub fn apply(
+        &self,
+        service_name: &str,
+        findings: Vec<Finding>,
+    ) -> (Vec<Finding>, Vec<SuppressedFinding>) {
+        let mut reported = Vec::new();
+        let mut suppressed = Vec::new();
+
+        for finding in findings {
+            match self.find(service_name, &finding).cloned() {
+                Some(suppression) => suppressed.push(SuppressedFinding {
+                    finding,
+                    reason: suppression.reason,
+                    expires: suppression.expires,
+                }),
+                None => reported.push(finding),
+            }
+        }
+
+        (reported, suppressed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use audit::Severity;
+
+    fn suppression(check: &str, expires: Option<&str>) -> Suppression {
+        Suppression {
+            service: String::from("api-*"),
+            check: check.to_string(),
+            expires: expires.map(String::from),
+            reason: Some(String::from("accepted")),
+        }
+    }
+
+    fn finding(check: &'static str) -> Finding {
+        Finding {
+            check,
+            severity: Severity::High,
+            detail: String::from("detail"),
+        }
+    }
+
+    #[test]
+    fn dates() {
+        assert!(is_date("2024-01-31"));
+        assert!(is_date("2024-02-29"));
+        assert!(is_date("2000-02-29"));
+        assert!(is_date("2024-12-31"));
+
+        assert!(!is_date("2024-13-45"));
+        assert!(!is_date("2024-00-10"));
+        assert!(!is_date("2024-04-31"));
+        assert!(!is_date("2024-01-00"));
+        assert!(!is_date("2023-02-29"));
+        assert!(!is_date("1900-02-29"));
+        assert!(!is_date("2024-1-31"));
+        assert!(!is_date("2024/01/31"));
+        assert!(!is_date("2024-01-31T00:00"));
+    }
+
+    #[test]
+    fn expiry() {
+        let today = helpers::today();
+        let suppressions = Suppressions(vec![
+            suppression("expired", Some("2000-01-01")),
+            suppression("later", Some("9999-12-31")),
+            suppression("today", Some(&today)),
+            suppression("forever", None),
+        ]);
+
+        let (reported, suppressed) = suppressions.apply(
+            "api-1",
+            vec![
+                finding("expired"),
+                finding("later"),
+                finding("today"),
+                finding("forever"),
+            ],
+        );
+
+        assert_eq!(
+            reported.iter().map(|f| f.check).collect::<Vec<&str>>(),
+            vec!["expired"]
+        );
+        assert_eq!(
+            suppressed
+                .iter()
+                .map(|s| s.finding.check)
+                .collect::<Vec<&str>>(),
+            vec!["later", "today", "forever"]
+        );
+        assert_eq!(suppressed[1].expires, Some(today));
+        assert_eq!(suppressed[1].reason, Some(String::from("accepted")));
+    }
+
+    #[test]
+    fn matching() {
+        let suppressions = Suppressions(vec![suppression("image-*", None)]);
+
+        assert!(suppressions.find("api-1", &finding("image-age")).is_some());
+        assert!(suppressions.find("web", &finding("image-age")).is_none());
+        assert!(suppressions.find("api-1", &finding("ecr-images")).is_none());
+    }
+}
@@ -0,0 +1,36 @@
+use failure::Error;
+use rusoto_ecs::Service;
+
+use super::{AuditCheck, AuditContext, Finding, Severity};
+use services;
+
+pub struct TargetGroups;
+
+impl AuditCheck for TargetGroups {
+    fn id(&self) -> &'static str {
+        "target-groups"
+    }
+
+    fn severity(&self) -> Severity {
+        Severity::High
+    }
+
+    fn description(&self) -> &'static str {
+        "Every target group attached to the service exists"
+    }
+
+    fn check(&self, ctx: &AuditContext, service: &Service) -> Result<Vec<Finding>, Error> {
+        let service_name = services::service_name(service)?;
+
+        Ok(services::service_target_groups(&ctx.elb_client, service)?
+            .into_iter()
+            .filter_map(|(arn, target_group)| target_group.err().map(|e| (arn, e)))
+            .map(|(arn, e)| {
+                self.finding(format!(
+                    "Target group {} attached to {} is invalid: {}",
+                    arn, service_name, e
+                ))
+            })
+            .collect())
+    }
+}
@@ -0,0 +1,184 @@
+use failure::Error;
+use rusoto_ecs::{ContainerDefinition, LoadBalancer, Service};
+use rusoto_elbv2::TargetGroup;
+
+use super::{AuditCheck, AuditContext, Finding, Severity};
+use services;
+
+pub struct TargetHealth;
+
+impl AuditCheck for TargetHealth {
+    fn id(&self) -> &'static str {
+        "target-health"
+    }
+
+    fn severity(&self) -> Severity {
+        Severity::High
+    }
+
+    fn description(&self) -> &'static str {
+        "Target groups have a healthy target per running task and health check the container's port"
+    }
+
+    fn check(&self, ctx: &AuditContext, service: &Service) -> Result<Vec<Finding>, Error> {
+        let target_groups = services::service_target_groups(&ctx.elb_client, service)?
+            .into_iter()
+            .filter_map(|(_, target_group)| target_group.ok())
+            .collect::<Vec<TargetGroup>>();
+
+        if target_groups.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let container_definitions = ctx
+            .task_definition(service)?
+            .and_then(|td| td.container_definitions)
+            .unwrap_or_default();
+        let load_balancers = service.load_balancers.clone().unwrap_or_default();
+        let running_count = service.running_count.unwrap_or(0);
+
+        let mut findings = Vec::new();
+
+        for target_group in target_groups {
+            let target_group_arn = match target_group.target_group_arn {
+                Some(ref target_group_arn) => target_group_arn.clone(),
+                None => continue,
+            };
+            let target_group_name = target_group
+                .target_group_name
+                .clone()
+                .unwrap_or_else(|| target_group_arn.clone());
+
+            let targets =
+                match services::describe_target_health(&ctx.elb_client, target_group_arn.clone()) {
+                    Ok(targets) => targets,
+                    Err(e) => {
+                        findings.push(self.finding(format!(
+                            "Can't describe the health of targets in {}: {}",
+                            target_group_name, e
+                        )));
+                        continue;
+                    }
+                };
+
+            for target in &targets {
+                let state = target
+                    .target_health
+                    .as_ref()
+                    .and_then(|h| h.state.clone())
+                    .unwrap_or_else(|| String::from("unknown"));
+
+                if state != "healthy" {
+                    let reason = target
+                        .target_health
+                        .as_ref()
+                        .and_then(|h| h.description.clone().or_else(|| h.reason.clone()))
+                        .unwrap_or_default();
+
+                    findings.push(self.finding(format!(
+                        "Target {} in {} is {}: {}",
+                        target
+                            .target
+                            .as_ref()
+                            .map(|t| format!("{}:{}", t.id, t.port.unwrap_or(0)))
+                            .unwrap_or_default(),
+                        target_group_name,
+                        state,
+                        reason
+                    )));
+                }
+            }
+
+            if (targets.len() as i64) < running_count {
+                findings.push(self.finding(format!(
+                    "{} has {} registered targets, but {} tasks are running",
+                    target_group_name,
+                    targets.len(),
+                    running_count
+                )));
+            }
+
+            for load_balancer in load_balancers
+                .iter()
+                .filter(|lb| lb.target_group_arn.as_ref() == Some(&target_group_arn))
+            {
+                findings.append(&mut self.health_check_findings(
+                    &target_group,
+                    &target_group_name,
+                    load_balancer,
+                    &container_definitions,
+                ));
+            }
+        }
+
+        Ok(findings)
+    }
+}
+
+impl TargetHealth {
+    fn health_check_findings(
+        &self,
+        target_group: &TargetGroup,
+        target_group_name: &str,
+        load_balancer: &LoadBalancer,
+        container_definitions: &[ContainerDefinition],
+    ) -> Vec<Finding> {
+        let container_name = load_balancer.container_name.clone().unwrap_or_default();
+        let container_port = match load_balancer.container_port {
+            Some(container_port) => container_port,
+            None => return Vec::new(),
+        };
+
+        let container_definition = match container_definitions
+            .iter()
+            .find(|cd| cd.name.as_ref() == Some(&container_name))
+        {
+            Some(container_definition) => container_definition,
+            None => {
+                return vec![self.finding(format!(
+                    "{} routes to container {}, which is not in the task definition",
+                    target_group_name, container_name
+                ))]
+            }
+        };
+
+        let port_mapping = container_definition
+            .port_mappings
+            .iter()
+            .flat_map(|pms| pms.iter())
+            .find(|pm| pm.container_port == Some(container_port));
+
+        let mut findings = Vec::new();
+
+        match port_mapping {
+            None => findings.push(self.finding(format!(
+                "{} routes to port {} of container {}, which has no such port mapping",
+                target_group_name, container_port, container_name
+            ))),
+            Some(port_mapping) => {
+                let health_check_port = target_group
+                    .health_check_port
+                    .clone()
+                    .unwrap_or_else(|| String::from("traffic-port"));
+
+                if health_check_port != "traffic-port" {
+                    let valid_ports = vec![Some(container_port), port_mapping.host_port]
+                        .into_iter()
+                        .flatten()
+                        .filter(|p| *p != 0)
+                        .map(|p| p.to_string())
+                        .collect::<Vec<String>>();
+
+                    if !valid_ports.contains(&health_check_port) {
+                        findings.push(self.finding(format!(
+                            "{} health checks port {}, but container {} listens on {}",
+                            target_group_name, health_check_port, container_name, container_port
+                        )));
+                    }
+                }
+            }
+        }
+
+        findings
+    }
+}
@@ -0,0 +1,242 @@
+use failure::Error;
+use rusoto_ecs::{ContainerDefinition, Service, TaskDefinition};
+use std::str::FromStr;
+
+use super::{AuditCheck, AuditContext, Finding, Severity};
+use images::ImageReference;
+
+/// Environment variable names that usually hold credentials
+const SECRET_NAMES: &[&str] = &[
+    "PASSWORD",
+    "PASSWD",
+    "SECRET",
+    "TOKEN",
+    "API_KEY",
+    "APIKEY",
+    "PRIVATE_KEY",
+    "ACCESS_KEY",
+    "CREDENTIAL",
+];
+
+/// Runs `check` against each container in the service's task definition,
+/// prefixing every returned detail with the container's name
+fn check_containers<F>(
+    ctx: &AuditContext,
+    service: &Service,
+    check: F,
+) -> Result<Vec<String>, Error>
+where
+    F: Fn(&TaskDefinition, &ContainerDefinition) -> Vec<String>,
+{
+    let task_definition = match ctx.task_definition(service)? {
+        Some(task_definition) => task_definition,
+        None => return Ok(Vec::new()),
+    };
+
+    Ok(task_definition
+        .container_definitions
+        .iter()
+        .flat_map(|cds| cds.iter())
+        .flat_map(|cd| {
+            let container_name = cd.name.clone().unwrap_or_default();
+            check(&task_definition, cd)
+                .into_iter()
+                .map(move |detail| format!("Container {}: {}", container_name, detail))
+        })
+        .collect())
+}
+
+pub struct MemoryLimits;
+
+impl AuditCheck for MemoryLimits {
+    fn id(&self) -> &'static str {
+        "memory-limits"
+    }
+
+    fn severity(&self) -> Severity {
+        Severity::Medium
+    }
+
+    fn description(&self) -> &'static str {
+        "Every container has a hard memory limit, or the task does"
+    }
+
+    fn check(&self, ctx: &AuditContext, service: &Service) -> Result<Vec<Finding>, Error> {
+        Ok(check_containers(ctx, service, |td, cd| {
+            if cd.memory.is_none() && td.memory.is_none() {
+                vec![String::from("No memory limit")]
+            } else {
+                Vec::new()
+            }
+        })?
+        .into_iter()
+        .map(|detail| self.finding(detail))
+        .collect())
+    }
+}
+
+pub struct LogConfiguration;
+
+impl AuditCheck for LogConfiguration {
+    fn id(&self) -> &'static str {
+        "log-configuration"
+    }
+
+    fn severity(&self) -> Severity {
+        Severity::Low
+    }
+
+    fn description(&self) -> &'static str {
+        "Every container ships its logs with a log driver"
+    }
+
+    fn check(&self, ctx: &AuditContext, service: &Service) -> Result<Vec<Finding>, Error> {
+        Ok(check_containers(ctx, service, |_, cd| {
+            if cd.log_configuration.is_none() {
+                vec![String::from("No log configuration")]
+            } else {
+                Vec::new()
+            }
+        })?
+        .into_iter()
+        .map(|detail| self.finding(detail))
+        .collect())
+    }
+}
+
+pub struct ImageTags;
+
+impl AuditCheck for ImageTags {
+    fn id(&self) -> &'static str {
+        "image-tags"
+    }
+
+    fn severity(&self) -> Severity {
+        Severity::Medium
+    }
+
+    fn description(&self) -> &'static str {
+        "Every container image is pinned to a tag other than `latest`, or a digest"
+    }
+
+    fn check(&self, ctx: &AuditContext, service: &Service) -> Result<Vec<Finding>, Error> {
+        Ok(check_containers(ctx, service, |_, cd| {
+            let image = match cd.image {
+                Some(ref image) => image,
+                None => return Vec::new(),
+            };
+
+            match ImageReference::from_str(image) {
+                Ok(ImageReference {
+                    digest: Some(_), ..
+                }) => Vec::new(),
+                Ok(ImageReference { tag: None, .. }) => {
+                    vec![format!("Image {} is untagged", image)]
+                }
+                Ok(ImageReference {
+                    tag: Some(ref tag), ..
+                }) if tag == "latest" => {
+                    vec![format!("Image {} uses the latest tag", image)]
+                }
+                Ok(_) => Vec::new(),
+                Err(e) => vec![format!("Image {} could not be parsed: {}", image, e)],
+            }
+        })?
+        .into_iter()
+        .map(|detail| self.finding(detail))
+        .collect())
+    }
+}
+
+pub struct Privileged;
+
+impl AuditCheck for Privileged {
+    fn id(&self) -> &'static str {
+        "privileged"
+    }
+
+    fn severity(&self) -> Severity {
+        Severity::High
+    }
+
+    fn description(&self) -> &'static str {
+        "No container runs in privileged mode"
+    }
+
+    fn check(&self, ctx: &AuditContext, service: &Service) -> Result<Vec<Finding>, Error> {
+        Ok(check_containers(ctx, service, |_, cd| {
+            if cd.privileged == Some(true) {
+                vec![String::from("Runs privileged")]
+            } else {
+                Vec::new()
+            }
+        })?
+        .into_iter()
+        .map(|detail| self.finding(detail))
+        .collect())
+    }
+}
+
+pub struct PlaintextSecrets;
+
+impl AuditCheck for PlaintextSecrets {
+    fn id(&self) -> &'static str {
+        "plaintext-secrets"
+    }
+
+    fn severity(&self) -> Severity {
+        Severity::Critical
+    }
+
+    fn description(&self) -> &'static str {
+        "No environment variable that looks like a credential has a plaintext value"
+    }
+
+    fn check(&self, ctx: &AuditContext, service: &Service) -> Result<Vec<Finding>, Error> {
+        Ok(check_containers(ctx, service, |_, cd| {
+            cd.environment
+                .iter()
+                .flat_map(|env| env.iter())
+                .filter(|kv| kv.value.as_ref().is_some_and(|v| !v.is_empty()))
+                .filter_map(|kv| kv.name.as_ref())
+                .filter(|name| {
+                    let name = name.to_uppercase();
+                    SECRET_NAMES.iter().any(|secret| name.contains(secret))
+                })
+                .map(|name| format!("Environment variable {} holds a plaintext value", name))
+                .collect()
+        })?
+        .into_iter()
+        .map(|detail| self.finding(detail))
+        .collect())
+    }
+}
+
+pub struct HealthChecks;
+
+impl AuditCheck for HealthChecks {
+    fn id(&self) -> &'static str {
+        "container-health-checks"
+    }
+
+    fn severity(&self) -> Severity {
+        Severity::Low
+    }
+
+    fn description(&self) -> &'static str {
+        "Every essential container defines a health check"
+    }
+
+    fn check(&self, ctx: &AuditContext, service: &Service) -> Result<Vec<Finding>, Error> {
+        Ok(check_containers(ctx, service, |_, cd| {
+            if cd.essential != Some(false) && cd.health_check.is_none() {
+                vec![String::from("No health check")]
+            } else {
+                Vec::new()
+            }
+        })?
+        .into_iter()
+        .map(|detail| self.finding(detail))
+        .collect())
+    }
+}
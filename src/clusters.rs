@@ -0,0 +1,450 @@
+use backoff;
+use failure::Error;
+use rusoto_core::Region;
+use rusoto_ecs::{
+    Cluster, ContainerInstance, DescribeClustersError, DescribeClustersRequest,
+    DescribeContainerInstancesError, DescribeContainerInstancesRequest, Ecs, EcsClient,
+    ListClustersError, ListClustersRequest, ListContainerInstancesError,
+    ListContainerInstancesRequest, Resource,
+};
+use std::collections::BTreeSet;
+
+use args::Targets;
+use helpers;
+
+/// The clusters to operate on in one region
+#[derive(Debug, Clone)]
+pub struct Target {
+    pub region: Region,
+    pub clusters: Vec<String>,
+}
+
+/// The cluster name at the end of a cluster ARN, or the input if it is already a name
+pub fn cluster_name(arn: &str) -> String {
+    arn.rsplit('/').next().unwrap_or(arn).to_string()
+}
+
+pub fn list_clusters(ecs_client: &EcsClient) -> Result<Vec<String>, Error> {
+    let mut token = Some(String::new());
+
+    let mut clusters = Vec::new();
+
+    while token.is_some() {
+        let res = helpers::retry_log("listing clusters", || {
+            ecs_client
+                .list_clusters(ListClustersRequest {
+                    max_results: None,
+                    next_token: token.clone(),
+                })
+                .sync()
+                .map_err(|e| match e {
+                    ListClustersError::Unknown(s) => {
+                        if s == r#"{"__type":"ThrottlingException","message":"Rate exceeded"}"# {
+                            backoff::Error::Transient(ListClustersError::Unknown(s))
+                        } else {
+                            backoff::Error::Permanent(ListClustersError::Unknown(s))
+                        }
+                    }
+                    _ => backoff::Error::Permanent(e),
+                })
+        })?;
+        if let Some(arns) = res.cluster_arns {
+            clusters.extend(arns.iter().map(|arn| cluster_name(arn)));
+        };
+
+        token = res.next_token;
+    }
+
+    clusters.sort();
+    Ok(clusters)
+}
+
+/// Expands the command line's cluster and region lists into the clusters to visit in each
+/// region. Cluster names containing `*` are matched against every cluster in the region
+pub fn resolve_targets(profile: Option<String>, targets: &Targets) -> Result<Vec<Target>, Error> {
+    targets
+        .region
+        .0
+        .iter()
+        .map(|region| {
+            let listed = if targets.cluster.0.iter().any(|c| c.contains('*')) {
+                list_clusters(&helpers::ecs_client(profile.clone(), region.clone())?)?
+            } else {
+                Vec::new()
+            };
+
+            let clusters = match_clusters(&targets.cluster.0, &listed);
+            if clusters.is_empty() {
+                warn!("No clusters in {} match {}", region.name(), targets.cluster);
+            }
+
+            Ok(Target {
+                region: region.clone(),
+                clusters,
+            })
+        })
+        .collect()
+}
+
+/// The clusters the patterns name, in the order given and without duplicates. Patterns
+/// containing `*` are matched against the listed clusters, other names are taken as they are
+fn match_clusters(patterns: &[String], listed: &[String]) -> Vec<String> {
+    let mut clusters: Vec<String> = Vec::new();
+    for pattern in patterns {
+        let matches = if pattern.contains('*') {
+            listed
+                .iter()
+                .filter(|c| helpers::glob_match(pattern, c))
+                .cloned()
+                .collect()
+        } else {
+            vec![pattern.clone()]
+        };
+
+        for cluster in matches {
+            if !clusters.contains(&cluster) {
+                clusters.push(cluster);
+            }
+        }
+    }
+
+    clusters
+}
+
+/// The region and name of every cluster the targets cover
+pub fn target_clusters(targets: &[Target]) -> impl Iterator<Item = (&str, &str)> {
+    targets.iter().flat_map(|target| {
+        target
+            .clusters
+            .iter()
+            .map(move |cluster| (target.region.name(), cluster.as_str()))
+    })
+}
+
+pub fn describe_cluster(ecs_client: &EcsClient, cluster: String) -> Result<Cluster, Error> {
+    let res = helpers::retry_log(format!("Describing {}", cluster), || {
+        ecs_client
+            .describe_clusters(DescribeClustersRequest {
+                clusters: Some(vec![cluster.clone()]),
+                include: Some(vec![String::from("STATISTICS")]),
+            })
+            .sync()
+            .map_err(|e| match e {
+                DescribeClustersError::Unknown(s) => {
+                    if s == r#"{"__type":"ThrottlingException","message":"Rate exceeded"}"# {
+                        backoff::Error::Transient(DescribeClustersError::Unknown(s))
+                    } else {
+                        backoff::Error::Permanent(DescribeClustersError::Unknown(s))
+                    }
+                }
+                _ => backoff::Error::Permanent(e),
+            })
+    })?;
+
+    if let Some(failures) = res.failures {
+        if !failures.is_empty() {
+            bail!("Failures: {:?}", failures);
+        }
+    }
+
+    match res.clusters.and_then(|mut clusters| clusters.pop()) {
+        None => bail!("No cluster description for {}", cluster),
+        Some(cluster) => Ok(cluster),
+    }
+}
+
+pub fn list_container_instances(
+    ecs_client: &EcsClient,
+    cluster: String,
+) -> Result<Vec<String>, Error> {
+    let mut token = Some(String::new());
+
+    let mut container_instances = Vec::new();
+
+    while token.is_some() {
+        let res = helpers::retry_log(
+            format!("listing container instances in {}", cluster),
+            || {
+                ecs_client
+                    .list_container_instances(ListContainerInstancesRequest {
+                        cluster: Some(cluster.clone()),
+                        filter: None,
+                        max_results: None,
+                        next_token: token.clone(),
+                        status: None,
+                    })
+                    .sync()
+                    .map_err(|e| match e {
+                        ListContainerInstancesError::Unknown(s) => {
+                            if s == r#"{"__type":"ThrottlingException","message":"Rate exceeded"}"#
+                            {
+                                backoff::Error::Transient(ListContainerInstancesError::Unknown(s))
+                            } else {
+                                backoff::Error::Permanent(ListContainerInstancesError::Unknown(s))
+                            }
+                        }
+                        _ => backoff::Error::Permanent(e),
+                    })
+            },
+        )?;
+        if let Some(mut arns) = res.container_instance_arns {
+            container_instances.append(&mut arns)
+        };
+
+        token = res.next_token;
+    }
+
+    Ok(container_instances)
+}
+
+pub fn describe_container_instances(
+    ecs_client: &EcsClient,
+    cluster: String,
+) -> Result<Vec<ContainerInstance>, Error> {
+    let arns = list_container_instances(ecs_client, cluster.clone())?;
+
+    let mut container_instances = Vec::new();
+
+    // DescribeContainerInstances takes at most 100 instances per call
+    for chunk in arns.chunks(100) {
+        let res = helpers::retry_log(
+            format!("Describing container instances in {}", cluster),
+            || {
+                ecs_client
+                    .describe_container_instances(DescribeContainerInstancesRequest {
+                        cluster: Some(cluster.clone()),
+                        container_instances: chunk.to_vec(),
+                    })
+                    .sync()
+                    .map_err(|e| match e {
+                        DescribeContainerInstancesError::Unknown(s) => {
+                            if s == r#"{"__type":"ThrottlingException","message":"Rate exceeded"}"#
+                            {
+                                backoff::Error::Transient(DescribeContainerInstancesError::Unknown(
+                                    s,
+                                ))
+                            } else {
+                                backoff::Error::Permanent(DescribeContainerInstancesError::Unknown(
+                                    s,
+                                ))
+                            }
+                        }
+                        _ => backoff::Error::Permanent(e),
+                    })
+            },
+        )?;
+
+        if let Some(failures) = res.failures {
+            if !failures.is_empty() {
+                bail!("Failures: {:?}", failures);
+            }
+        }
+
+        if let Some(mut instances) = res.container_instances {
+            container_instances.append(&mut instances);
+        }
+    }
+
+    Ok(container_instances)
+}
+
+/// Registered and reserved amounts of one resource summed over a cluster's container instances
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Reservation {
+    pub registered: i64,
+    pub reserved: i64,
+}
+
+impl Reservation {
+    fn add(&mut self, name: &str, instance: &ContainerInstance) {
+        let amount = |resources: &Option<Vec<Resource>>| {
+            resources
+                .iter()
+                .flat_map(|rs| rs.iter())
+                .filter(|r| r.name.as_ref().is_some_and(|n| n == name))
+                .filter_map(|r| r.integer_value)
+                .sum::<i64>()
+        };
+
+        let registered = amount(&instance.registered_resources);
+        self.registered += registered;
+        self.reserved += registered - amount(&instance.remaining_resources);
+    }
+
+    pub fn percentage(&self) -> f64 {
+        if self.registered == 0 {
+            0.0
+        } else {
+            100.0 * self.reserved as f64 / self.registered as f64
+        }
+    }
+}
+
+/// The state of a cluster and the container instances registered to it
+#[derive(Debug, Clone)]
+pub struct ClusterSummary {
+    pub cluster: Cluster,
+    /// CPU units
+    pub cpu: Reservation,
+    /// Memory in MiB
+    pub memory: Reservation,
+    pub instance_types: BTreeSet<String>,
+    pub agent_versions: BTreeSet<String>,
+    pub docker_versions: BTreeSet<String>,
+}
+
+impl ClusterSummary {
+    /// Labelled values, in the order they are printed
+    pub fn properties(&self) -> Vec<(&'static str, String)> {
+        let mut properties = self.settings();
+        properties.extend(self.counters());
+        properties
+    }
+
+    /// How the cluster is set up, which clusters meant to match should agree on
+    pub fn settings(&self) -> Vec<(&'static str, String)> {
+        let join = |set: &BTreeSet<String>| set.iter().cloned().collect::<Vec<String>>().join(", ");
+
+        vec![
+            ("Status", self.cluster.status.clone().unwrap_or_default()),
+            ("Instance types", join(&self.instance_types)),
+            ("Agent versions", join(&self.agent_versions)),
+            ("Docker versions", join(&self.docker_versions)),
+        ]
+    }
+
+    /// Live counts and usage, which change all the time and differ between any two clusters
+    pub fn counters(&self) -> Vec<(&'static str, String)> {
+        let count = |c: Option<i64>| c.unwrap_or_default().to_string();
+
+        vec![
+            (
+                "Container instances",
+                count(self.cluster.registered_container_instances_count),
+            ),
+            ("Running tasks", count(self.cluster.running_tasks_count)),
+            ("Pending tasks", count(self.cluster.pending_tasks_count)),
+            ("Active services", count(self.cluster.active_services_count)),
+            (
+                "CPU reserved",
+                format!(
+                    "{} of {} units ({:.1}%)",
+                    self.cpu.reserved,
+                    self.cpu.registered,
+                    self.cpu.percentage()
+                ),
+            ),
+            (
+                "Memory reserved",
+                format!(
+                    "{} of {} MiB ({:.1}%)",
+                    self.memory.reserved,
+                    self.memory.registered,
+                    self.memory.percentage()
+                ),
+            ),
+        ]
+    }
+}
+
+pub fn cluster_summary(ecs_client: &EcsClient, cluster: String) -> Result<ClusterSummary, Error> {
+    let mut summary = ClusterSummary {
+        cluster: describe_cluster(ecs_client, cluster.clone())?,
+        cpu: Reservation::default(),
+        memory: Reservation::default(),
+        instance_types: BTreeSet::new(),
+        agent_versions: BTreeSet::new(),
+        docker_versions: BTreeSet::new(),
+    };
+
+    for instance in describe_container_instances(ecs_client, cluster)? {
+        summary.cpu.add("CPU", &instance);
+        summary.memory.add("MEMORY", &instance);
+
+        if let Some(instance_type) = instance
+            .attributes
+            .iter()
+            .flat_map(|attrs| attrs.iter())
+            .find(|a| a.name == "ecs.instance-type")
+            .and_then(|a| a.value.clone())
+        {
+            summary.instance_types.insert(instance_type);
+        }
+
+        if let Some(ref version_info) = instance.version_info {
+            summary
+                .agent_versions
+                .extend(version_info.agent_version.clone());
+            summary
+                .docker_versions
+                .extend(version_info.docker_version.clone());
+        }
+    }
+
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn matches_cluster_patterns() {
+        let listed = names(&["prod-api", "prod-web", "staging-api"]);
+
+        assert_eq!(
+            match_clusters(&names(&["prod-*"]), &listed),
+            names(&["prod-api", "prod-web"])
+        );
+        assert_eq!(match_clusters(&names(&["*"]), &listed), listed);
+        assert_eq!(
+            match_clusters(&names(&["*-api", "prod-*"]), &listed),
+            names(&["prod-api", "staging-api", "prod-web"])
+        );
+    }
+
+    #[test]
+    fn takes_plain_names_as_given() {
+        assert_eq!(
+            match_clusters(&names(&["other", "prod-api"]), &[]),
+            names(&["other", "prod-api"])
+        );
+        assert_eq!(
+            match_clusters(&names(&["prod-api", "prod-*"]), &names(&["prod-api"])),
+            names(&["prod-api"])
+        );
+    }
+
+    #[test]
+    fn matches_nothing() {
+        let listed = names(&["prod-api"]);
+
+        assert!(match_clusters(&names(&["dev-*"]), &listed).is_empty());
+        assert!(match_clusters(&names(&["*"]), &[]).is_empty());
+    }
+
+    #[test]
+    fn target_cluster_pairs() {
+        let targets = vec![
+            Target {
+                region: Region::UsEast1,
+                clusters: names(&["a", "b"]),
+            },
+            Target {
+                region: Region::EuWest1,
+                clusters: names(&["a"]),
+            },
+        ];
+
+        assert_eq!(
+            target_clusters(&targets).collect::<Vec<(&str, &str)>>(),
+            vec![("us-east-1", "a"), ("us-east-1", "b"), ("eu-west-1", "a")]
+        );
+        assert!(helpers::is_fleet(target_clusters(&targets)));
+        assert!(!helpers::is_fleet(target_clusters(&targets[1..])));
+    }
+}
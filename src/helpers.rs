@@ -8,6 +8,8 @@ use rusoto_ecs::EcsClient;
 use rusoto_elbv2::ElbClient;
 
 use std::fmt::Display;
+use std::io::{self, BufRead, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
 
 pub fn retry_log<S, T, E, F>(msg: S, mut op: F) -> Result<T, backoff::Error<E>>
 where
@@ -20,6 +22,102 @@ where
     })
 }
 
+/// Asks a yes/no question on the terminal, defaulting to no. The question goes to stderr so
+/// it doesn't end up in output that is redirected
+pub fn confirm(question: &str) -> Result<bool, Error> {
+    eprint!("{} [y/N] ", question);
+    io::stderr().flush()?;
+
+    let mut answer = String::new();
+    io::stdin().lock().read_line(&mut answer)?;
+
+    Ok(["y", "yes"].contains(&answer.trim().to_lowercase().as_str()))
+}
+
+/// Asks for something to be typed on the terminal, such as the name of what is about to be
+/// deleted, and whether it was typed exactly
+pub fn confirm_typed(prompt: &str, expected: &str) -> Result<bool, Error> {
+    eprint!("{} ", prompt);
+    io::stderr().flush()?;
+
+    let mut answer = String::new();
+    io::stdin().lock().read_line(&mut answer)?;
+
+    Ok(answer.trim() == expected)
+}
+
+/// Minutes elapsed since an AWS timestamp (seconds since the epoch)
+pub fn minutes_since(timestamp: f64) -> f64 {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as f64)
+        .unwrap_or(timestamp);
+
+    (now - timestamp) / 60.0
+}
+
+/// Today's UTC date as `YYYY-MM-DD`
+pub fn today() -> String {
+    let days = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() / 86_400)
+        .unwrap_or(0) as i64;
+
+    civil_date(days)
+}
+
+/// Converts days since 1970-01-01 to a `YYYY-MM-DD` date, see
+/// http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+fn civil_date(days: i64) -> String {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+/// Whether output about these (region, cluster) pairs covers more than one cluster, in which
+/// case it is qualified with the region and cluster it came from
+pub fn is_fleet<'a, I>(clusters: I) -> bool
+where
+    I: IntoIterator<Item = (&'a str, &'a str)>,
+{
+    let mut clusters = clusters.into_iter();
+    match clusters.next() {
+        Some(first) => clusters.any(|cluster| cluster != first),
+        None => false,
+    }
+}
+
+/// Matches `name` against a pattern where `*` stands for any run of characters
+pub fn glob_match(pattern: &str, name: &str) -> bool {
+    let parts = pattern.split('*').collect::<Vec<&str>>();
+    if parts.len() == 1 {
+        return pattern == name;
+    }
+
+    let (first, last) = (parts[0], parts[parts.len() - 1]);
+    if !name.starts_with(first) || !name[first.len()..].ends_with(last) {
+        return false;
+    }
+
+    let mut rest = &name[first.len()..name.len() - last.len()];
+    for part in &parts[1..parts.len() - 1] {
+        match rest.find(part) {
+            Some(i) => rest = &rest[i + part.len()..],
+            None => return false,
+        }
+    }
+
+    true
+}
+
 pub fn credentials_provider(profile: Option<String>) -> Result<ChainProvider, Error> {
     match profile {
         Some(profile) => Ok(ChainProvider::with_profile_provider({
@@ -54,3 +152,55 @@ pub fn ecr_client(profile: Option<String>, region: Region) -> Result<EcrClient,
         region,
     ))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn civil_dates() {
+        assert_eq!(civil_date(0), "1970-01-01");
+        assert_eq!(civil_date(59), "1970-03-01");
+        assert_eq!(civil_date(-1), "1969-12-31");
+        assert_eq!(civil_date(10_957), "2000-01-01");
+        assert_eq!(civil_date(11_016), "2000-02-29");
+        assert_eq!(civil_date(11_017), "2000-03-01");
+        assert_eq!(civil_date(19_782), "2024-02-29");
+        assert_eq!(civil_date(47_540), "2100-02-28");
+        assert_eq!(civil_date(47_541), "2100-03-01");
+    }
+
+    #[test]
+    fn fleets() {
+        assert!(!is_fleet(Vec::new()));
+        assert!(!is_fleet(vec![("us-east-1", "prod")]));
+        assert!(!is_fleet(vec![
+            ("us-east-1", "prod"),
+            ("us-east-1", "prod")
+        ]));
+        assert!(is_fleet(vec![
+            ("us-east-1", "prod"),
+            ("us-east-1", "staging")
+        ]));
+        assert!(is_fleet(vec![("us-east-1", "prod"), ("eu-west-1", "prod")]));
+    }
+
+    #[test]
+    fn glob_matches() {
+        assert!(glob_match("api", "api"));
+        assert!(!glob_match("api", "api-2"));
+        assert!(glob_match("*", ""));
+        assert!(glob_match("*", "anything"));
+        assert!(glob_match("prod-*", "prod-eu"));
+        assert!(glob_match("prod-*", "prod-"));
+        assert!(!glob_match("prod-*", "staging-eu"));
+        assert!(glob_match("*-eu", "prod-eu"));
+        assert!(glob_match("prod-*-eu", "prod-web-eu"));
+        assert!(!glob_match("prod-*-eu", "prod-eu"));
+        assert!(glob_match("a*b*c", "abc"));
+        assert!(glob_match("a*b*c", "axxbyyc"));
+        assert!(!glob_match("a*b*c", "acb"));
+        assert!(glob_match("a**b", "ab"));
+        assert!(!glob_match("aa*aa", "aaa"));
+    }
+}
@@ -0,0 +1,274 @@
+use failure::Error;
+use std::fmt;
+use std::str::FromStr;
+
+/// A container image reference such as
+/// `123456789012.dkr.ecr.us-east-1.amazonaws.com/team/app:v1.2.3` or `nginx@sha256:...`
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImageReference {
+    /// The registry host, absent for Docker Hub images
+    pub registry: Option<String>,
+    /// The repository path within the registry, e.g. `team/app`
+    pub repository: String,
+    pub tag: Option<String>,
+    pub digest: Option<String>,
+}
+
+/// The ECR registry that hosts an image
+#[derive(Debug, Clone, PartialEq)]
+pub struct EcrRegistry {
+    pub account_id: String,
+    pub region: String,
+}
+
+impl ImageReference {
+    /// The ECR registry this image lives in, or `None` for any other registry
+    pub fn ecr_registry(&self) -> Option<EcrRegistry> {
+        let registry = self.registry.as_ref()?;
+        let parts = registry.split('.').collect::<Vec<&str>>();
+
+        // <account>.dkr.ecr[-fips].<region>.amazonaws.com[.cn]
+        if parts.len() >= 5
+            && parts[0].len() == 12
+            && parts[0].chars().all(|c| c.is_ascii_digit())
+            && parts[1] == "dkr"
+            && parts[2].starts_with("ecr")
+            && parts[4] == "amazonaws"
+        {
+            Some(EcrRegistry {
+                account_id: parts[0].to_string(),
+                region: parts[3].to_string(),
+            })
+        } else {
+            None
+        }
+    }
+
+    /// The tag that is pulled when neither a tag nor a digest is given
+    pub fn tag_or_latest(&self) -> String {
+        self.tag.clone().unwrap_or_else(|| String::from("latest"))
+    }
+}
+
+/// The image a container should run when asked to switch to `new`, where a `new` of just
+/// `:<tag>` keeps the current repository and changes only its tag
+pub fn replace_image(current: &str, new: &str) -> Result<String, Error> {
+    if !new.starts_with(':') {
+        return Ok(new.to_string());
+    }
+
+    let tag = &new[1..];
+    if tag.is_empty() || tag.contains('/') || tag.contains('@') {
+        bail!("Invalid tag {:?}, expected :<tag>", new);
+    }
+
+    let image = ImageReference {
+        tag: Some(tag.to_string()),
+        digest: None,
+        ..ImageReference::from_str(current)?
+    };
+
+    Ok(image.to_string())
+}
+
+impl FromStr for ImageReference {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, digest) = match s.find('@') {
+            Some(i) => (&s[..i], Some(s[i + 1..].to_string())),
+            None => (s, None),
+        };
+
+        // Like Docker, the first component is only a registry when it looks like a host
+        let (registry, path) = match name.find('/') {
+            Some(i)
+                if name[..i].contains('.')
+                    || name[..i].contains(':')
+                    || &name[..i] == "localhost" =>
+            {
+                (Some(name[..i].to_string()), &name[i + 1..])
+            }
+            _ => (None, name),
+        };
+
+        let last_component = path.rfind('/').map_or(0, |i| i + 1);
+        let (repository, tag) = match path[last_component..].find(':') {
+            Some(i) => (
+                &path[..last_component + i],
+                Some(path[last_component + i + 1..].to_string()),
+            ),
+            None => (path, None),
+        };
+
+        if repository.is_empty() {
+            bail!("No repository in image reference {:?}", s);
+        }
+
+        Ok(ImageReference {
+            registry,
+            repository: repository.to_string(),
+            tag,
+            digest,
+        })
+    }
+}
+
+impl fmt::Display for ImageReference {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(ref registry) = self.registry {
+            write!(f, "{}/", registry)?;
+        }
+        write!(f, "{}", self.repository)?;
+        if let Some(ref tag) = self.tag {
+            write!(f, ":{}", tag)?;
+        }
+        if let Some(ref digest) = self.digest {
+            write!(f, "@{}", digest)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(s: &str) -> ImageReference {
+        ImageReference::from_str(s).unwrap()
+    }
+
+    #[test]
+    fn docker_hub_images() {
+        let image = parse("nginx");
+        assert_eq!(image.registry, None);
+        assert_eq!(image.repository, "nginx");
+        assert_eq!(image.tag, None);
+        assert_eq!(image.tag_or_latest(), "latest");
+        assert_eq!(image.ecr_registry(), None);
+
+        let image = parse("library/nginx:1.25");
+        assert_eq!(image.registry, None);
+        assert_eq!(image.repository, "library/nginx");
+        assert_eq!(image.tag, Some(String::from("1.25")));
+    }
+
+    #[test]
+    fn digests() {
+        let image = parse("nginx@sha256:abc");
+        assert_eq!(image.repository, "nginx");
+        assert_eq!(image.tag, None);
+        assert_eq!(image.digest, Some(String::from("sha256:abc")));
+
+        let image = parse("nginx:1.25@sha256:abc");
+        assert_eq!(image.tag, Some(String::from("1.25")));
+        assert_eq!(image.digest, Some(String::from("sha256:abc")));
+    }
+
+    #[test]
+    fn nested_repositories() {
+        let image = parse("123456789012.dkr.ecr.us-east-1.amazonaws.com/team/sub/app:v1.2.3");
+        assert_eq!(
+            image.registry,
+            Some(String::from("123456789012.dkr.ecr.us-east-1.amazonaws.com"))
+        );
+        assert_eq!(image.repository, "team/sub/app");
+        assert_eq!(image.tag, Some(String::from("v1.2.3")));
+    }
+
+    #[test]
+    fn registries_with_ports() {
+        let image = parse("registry.example.com:5000/app");
+        assert_eq!(
+            image.registry,
+            Some(String::from("registry.example.com:5000"))
+        );
+        assert_eq!(image.repository, "app");
+        assert_eq!(image.tag, None);
+
+        let image = parse("localhost:5000/team/app:dev");
+        assert_eq!(image.registry, Some(String::from("localhost:5000")));
+        assert_eq!(image.repository, "team/app");
+        assert_eq!(image.tag, Some(String::from("dev")));
+        assert_eq!(image.ecr_registry(), None);
+    }
+
+    #[test]
+    fn ecr_registries() {
+        assert_eq!(
+            parse("123456789012.dkr.ecr.eu-west-1.amazonaws.com/app:v1").ecr_registry(),
+            Some(EcrRegistry {
+                account_id: String::from("123456789012"),
+                region: String::from("eu-west-1"),
+            })
+        );
+        assert_eq!(
+            parse("123456789012.dkr.ecr-fips.us-gov-west-1.amazonaws.com/app").ecr_registry(),
+            Some(EcrRegistry {
+                account_id: String::from("123456789012"),
+                region: String::from("us-gov-west-1"),
+            })
+        );
+        assert_eq!(
+            parse("123456789012.dkr.ecr.cn-north-1.amazonaws.com.cn/app").ecr_registry(),
+            Some(EcrRegistry {
+                account_id: String::from("123456789012"),
+                region: String::from("cn-north-1"),
+            })
+        );
+        assert_eq!(
+            parse("12345.dkr.ecr.us-east-1.amazonaws.com/app").ecr_registry(),
+            None
+        );
+        assert_eq!(parse("quay.io/team/app").ecr_registry(), None);
+    }
+
+    #[test]
+    fn empty_repositories() {
+        assert!(ImageReference::from_str("").is_err());
+        assert!(ImageReference::from_str("registry.example.com/").is_err());
+    }
+
+    #[test]
+    fn display_round_trips() {
+        for s in &[
+            "nginx",
+            "nginx:1.25",
+            "nginx@sha256:abc",
+            "localhost:5000/team/app:dev",
+            "123456789012.dkr.ecr.us-east-1.amazonaws.com/team/app:v1@sha256:abc",
+        ] {
+            assert_eq!(parse(s).to_string(), *s);
+        }
+    }
+
+    #[test]
+    fn replaced_images() {
+        assert_eq!(
+            replace_image("nginx:1.25", "httpd:2.4").unwrap(),
+            "httpd:2.4"
+        );
+        assert_eq!(replace_image("nginx:1.25", ":1.26").unwrap(), "nginx:1.26");
+        assert_eq!(replace_image("nginx", ":1.26").unwrap(), "nginx:1.26");
+        assert_eq!(
+            replace_image("nginx:1.25@sha256:abc", ":1.26").unwrap(),
+            "nginx:1.26"
+        );
+        assert_eq!(
+            replace_image("localhost:5000/team/app:dev", ":v2").unwrap(),
+            "localhost:5000/team/app:v2"
+        );
+        assert_eq!(
+            replace_image("registry.example.com:5000/app", ":v2").unwrap(),
+            "registry.example.com:5000/app:v2"
+        );
+    }
+
+    #[test]
+    fn invalid_replacement_tags() {
+        assert!(replace_image("nginx:1.25", ":").is_err());
+        assert!(replace_image("nginx:1.25", ":team/app").is_err());
+        assert!(replace_image("nginx:1.25", ":v1@sha256:abc").is_err());
+        assert!(replace_image("", ":v1").is_err());
+    }
+}
@@ -0,0 +1,271 @@
+use failure::Error;
+use serde::de::DeserializeOwned;
+use serde_json::{self, Value};
+use serde_yaml;
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Read};
+use std::path::Path;
+use std::str::FromStr;
+use toml;
+
+arg_enum! {
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub enum InputFormat {
+        Json,
+        Yaml,
+        Toml,
+    }
+}
+
+/// The contents of a file, or of stdin for `-`
+pub fn read_source(source: &str) -> Result<String, Error> {
+    if source == "-" {
+        let mut text = String::new();
+        io::stdin().read_to_string(&mut text)?;
+        Ok(text)
+    } else {
+        fs::read_to_string(source).map_err(|e| format_err!("Can't read {}: {}", source, e))
+    }
+}
+
+/// The format to parse a source as: the one given, else the one its extension names, else JSON
+pub fn source_format(source: &str, format: Option<InputFormat>) -> InputFormat {
+    if let Some(format) = format {
+        return format;
+    }
+
+    match Path::new(source)
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_lowercase())
+    {
+        Some(ref e) if e == "yaml" || e == "yml" => InputFormat::Yaml,
+        Some(ref e) if e == "toml" => InputFormat::Toml,
+        _ => InputFormat::Json,
+    }
+}
+
+pub fn parse<T: DeserializeOwned>(
+    source: &str,
+    text: &str,
+    format: Option<InputFormat>,
+) -> Result<T, Error> {
+    let format = source_format(source, format);
+    let parsed = match format {
+        InputFormat::Json => serde_json::from_str(text).map_err(Error::from),
+        InputFormat::Yaml => serde_yaml::from_str(text).map_err(Error::from),
+        InputFormat::Toml => toml::from_str(text).map_err(Error::from),
+    };
+
+    parsed.map_err(|e| format_err!("Can't parse {} as {}: {}", source, format, e))
+}
+
+/// A service name -> value mapping read from a file or stdin. It is only read once the cluster
+/// and input format are known, see `resolve`, as clap parses arguments more than once and stdin
+/// can only be read once
+#[derive(Debug, Clone)]
+pub enum ServiceMap<T> {
+    Unparsed(String),
+    Parsed(HashMap<String, T>),
+}
+
+impl<T> FromStr for ServiceMap<T> {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s != "-" && !Path::new(s).is_file() {
+            bail!("{} is not a file", s);
+        }
+
+        Ok(ServiceMap::Unparsed(s.to_string()))
+    }
+}
+
+impl<T: DeserializeOwned> ServiceMap<T> {
+    /// Reads and parses the mapping and picks out the entries for one cluster
+    pub fn resolve(
+        &mut self,
+        format: Option<InputFormat>,
+        region: &str,
+        cluster: &str,
+    ) -> Result<(), Error> {
+        let map = match *self {
+            ServiceMap::Parsed(_) => return Ok(()),
+            ServiceMap::Unparsed(ref source) => cluster_entries(
+                source,
+                parse(source, &read_source(source)?, format)?,
+                region,
+                cluster,
+            )?,
+        };
+
+        *self = ServiceMap::Parsed(map);
+        Ok(())
+    }
+}
+
+type ByName<T> = HashMap<String, T>;
+
+/// Picks the entries for one cluster out of a service name -> value mapping, which is either
+/// for one cluster, by cluster, or by region and then cluster as `export` prints it for several
+/// clusters
+fn cluster_entries<T: DeserializeOwned>(
+    source: &str,
+    value: Value,
+    region: &str,
+    cluster: &str,
+) -> Result<HashMap<String, T>, Error> {
+    let services_error = match serde_json::from_value(value.clone()) {
+        Ok(services) => return Ok(services),
+        Err(e) => e,
+    };
+
+    let clusters_error = match serde_json::from_value::<ByName<ByName<T>>>(value.clone()) {
+        Ok(mut clusters) => {
+            return clusters.remove(cluster).ok_or(format_err!(
+                "{} has no entry for cluster {}",
+                source,
+                cluster
+            ))
+        }
+        Err(e) => e,
+    };
+    let regions_error = match serde_json::from_value::<ByName<ByName<ByName<T>>>>(value.clone()) {
+        Ok(mut regions) => {
+            return regions
+                .remove(region)
+                .and_then(|mut clusters| clusters.remove(cluster))
+                .ok_or(format_err!(
+                    "{} has no entry for {}/{}",
+                    source,
+                    region,
+                    cluster
+                ))
+        }
+        Err(e) => e,
+    };
+
+    // None of the shapes fit, so report the error for the one this cluster's entries are in
+    let error = if value.get(cluster).is_some() {
+        clusters_error
+    } else if value.get(region).and_then(|c| c.get(cluster)).is_some() {
+        regions_error
+    } else {
+        services_error
+    };
+
+    Err(format_err!("Can't parse {}: {}", source, error))
+}
+
+impl<T> ServiceMap<T> {
+    /// The value for a service. Always `None` until the map is resolved
+    pub fn get(&self, service_name: &str) -> Option<&T> {
+        match *self {
+            ServiceMap::Parsed(ref map) => map.get(service_name),
+            ServiceMap::Unparsed(_) => None,
+        }
+    }
+
+    /// The mapped service names. Empty until the map is resolved
+    pub fn service_names(&self) -> Vec<&String> {
+        match *self {
+            ServiceMap::Parsed(ref map) => map.keys().collect(),
+            ServiceMap::Unparsed(_) => Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::process;
+
+    fn resolve(name: &str, text: &str, cluster: &str) -> Result<ServiceMap<i64>, Error> {
+        let path = env::temp_dir().join(format!("ecs-manage-{}-{}", process::id(), name));
+        fs::write(&path, text)?;
+
+        let mut map = ServiceMap::from_str(path.to_str().unwrap())?;
+        let resolved = map.resolve(None, "us-east-1", cluster);
+        fs::remove_file(&path)?;
+
+        resolved.map(|_| map)
+    }
+
+    #[test]
+    fn flat_maps() {
+        let map = resolve("flat.json", r#"{"api": 2, "web": 3}"#, "prod").unwrap();
+        assert_eq!(map.get("api"), Some(&2));
+        assert_eq!(map.get("web"), Some(&3));
+        assert_eq!(map.get("worker"), None);
+    }
+
+    #[test]
+    fn maps_by_cluster() {
+        let text = r#"{"prod": {"api": 2}, "staging": {"api": 1}}"#;
+        let map = resolve("clusters.json", text, "prod").unwrap();
+        assert_eq!(map.get("api"), Some(&2));
+
+        let map = resolve("clusters.yaml", "prod:\n  api: 4\n", "prod").unwrap();
+        assert_eq!(map.get("api"), Some(&4));
+    }
+
+    #[test]
+    fn maps_by_region() {
+        let text = r#"{"us-east-1": {"prod": {"api": 2}}, "eu-west-1": {"prod": {"api": 5}}}"#;
+        let map = resolve("regions.json", text, "prod").unwrap();
+        assert_eq!(map.get("api"), Some(&2));
+    }
+
+    #[test]
+    fn missing_entries() {
+        let text = r#"{"staging": {"api": 1}}"#;
+        let error = resolve("no-cluster.json", text, "prod").unwrap_err();
+        assert!(
+            error.to_string().ends_with("has no entry for cluster prod"),
+            "{}",
+            error
+        );
+
+        let text = r#"{"eu-west-1": {"prod": {"api": 5}}}"#;
+        let error = resolve("no-region.json", text, "prod").unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .ends_with("has no entry for us-east-1/prod"),
+            "{}",
+            error
+        );
+    }
+
+    #[test]
+    fn value_type_errors() {
+        let error = resolve("flat-type.json", r#"{"api": "two"}"#, "prod").unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .contains(r#"invalid type: string "two", expected i64"#),
+            "{}",
+            error
+        );
+
+        let text = r#"{"prod": {"api": "two"}, "staging": {"api": 1}}"#;
+        let error = resolve("cluster-type.json", text, "prod").unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .contains(r#"invalid type: string "two", expected i64"#),
+            "{}",
+            error
+        );
+
+        let text = r#"{"us-east-1": {"prod": {"api": 2.5}}}"#;
+        let error = resolve("region-type.json", text, "prod").unwrap_err();
+        assert!(
+            error.to_string().contains("invalid type: floating point"),
+            "{}",
+            error
+        );
+    }
+}
@@ -12,30 +12,49 @@ extern crate failure;
 extern crate backoff;
 #[macro_use]
 extern crate log;
-extern crate stderrlog;
-#[macro_use]
-extern crate maplit;
 extern crate itertools;
 extern crate serde;
+extern crate stderrlog;
+#[macro_use]
+extern crate serde_derive;
+#[macro_use]
 extern crate serde_json;
+extern crate serde_yaml;
+extern crate toml;
 
 mod args;
+mod audit;
+mod clusters;
 mod helpers;
+mod images;
+mod input;
+mod manifest;
+mod pause;
 mod services;
+mod task_definitions;
+mod tasks;
 
 use failure::Error;
 use serde_json::Number as JsonNumber;
 use serde_json::Value;
 use serde_json::Value::Number;
-use std::collections::HashMap;
+use std::collections::HashSet;
+use std::process;
 use std::thread;
 use std::time::Duration;
 use structopt::StructOpt;
 
 use args::Args;
+use args::ClustersCommand::{
+    Compare as CompareClusters, Info as ClusterInfo, List as ListClusters,
+};
 use args::EcsCommand::*;
-use args::ServiceProperty;
 use args::ServicesCommand::*;
+use args::TaskDefinitionsCommand::{
+    BumpImage, DeregisterUnused, Diff as DiffTaskDefinitions, List as ListTaskDefinitions,
+};
+use args::TasksCommand::{List as ListTasks, Stop as StopTasks};
+use args::{DesiredCountOptions, ServiceModification, ServiceProperty};
 
 fn main() -> Result<(), Error> {
     let args = Args::from_args();
@@ -49,43 +68,247 @@ fn main() -> Result<(), Error> {
 
     match args.command {
         ServicesCommand {
-            command: Info { cluster, region },
+            command: Info { targets },
         } => {
+            let targets = clusters::resolve_targets(args.profile.clone(), &targets)?;
+            let fleet = helpers::is_fleet(clusters::target_clusters(&targets));
+
+            for target in targets {
+                let ecs_client = helpers::ecs_client(args.profile.clone(), target.region.clone())?;
+                for cluster in target.clusters {
+                    if fleet {
+                        println!("# {} {}", target.region.name(), cluster);
+                    }
+
+                    for service in services::describe_services(&ecs_client, cluster.clone())? {
+                        let service_name = services::service_name(&service)?;
+
+                        println!(
+                            "{}/{} - Task: {} - Desired Count: {}",
+                            cluster,
+                            service_name,
+                            service.task_definition.ok_or(format_err!(
+                                "Service {:?} has no task definition",
+                                &service_name
+                            ))?,
+                            service.desired_count.ok_or(format_err!(
+                                "Service {} has no desired count",
+                                service_name
+                            ))?,
+                        );
+                    }
+                }
+            }
+        }
+        ServicesCommand {
+            command:
+                Audit {
+                    targets,
+                    checks,
+                    skip_checks,
+                    config,
+                    suppressions,
+                    output,
+                    fail_on,
+                    fix,
+                    yes,
+                },
+        } => {
+            let config = config
+                .unwrap_or_default()
+                .with_overrides(checks, skip_checks);
+            let checks = audit::select_checks(&config)?;
+
+            let suppressions = suppressions.unwrap_or_default();
+
+            let profile = args.profile;
+            let targets = clusters::resolve_targets(profile.clone(), &targets)?;
+            let contexts = targets
+                .iter()
+                .map(|target| audit::AuditContext::new(profile.clone(), target.region.clone()))
+                .collect::<Result<Vec<audit::AuditContext>, Error>>()?;
+
+            let mut reports = Vec::new();
+            let mut plan = Vec::new();
+            for (target, ctx) in targets.iter().zip(contexts.iter()) {
+                for cluster in &target.clusters {
+                    for service in services::describe_services(&ctx.ecs_client, cluster.clone())? {
+                        let report =
+                            audit::audit_service(ctx, &checks, &suppressions, cluster, &service)?;
+
+                        if fix {
+                            if let Some(remediation) =
+                                audit::plan_remediation(ctx, &service, &report)?
+                            {
+                                plan.push((ctx, remediation));
+                            }
+                        }
+
+                        reports.push(report);
+                    }
+                }
+            }
+
+            print!("{}", audit::render(output, &checks, &reports)?);
+
+            if fix {
+                if plan.is_empty() {
+                    eprintln!("Nothing to fix");
+                } else {
+                    eprintln!("Remediation plan:");
+                    for (_, remediation) in &plan {
+                        eprintln!("  {}", remediation);
+                    }
+
+                    if yes || helpers::confirm(&format!("Apply {} remediations?", plan.len()))? {
+                        for (ctx, remediation) in &plan {
+                            audit::apply_remediation(ctx, remediation)?;
+                        }
+                    }
+                }
+            }
+
+            if let Some(fail_on) = fail_on {
+                if audit::has_findings_at(&reports, fail_on) {
+                    process::exit(1);
+                }
+            }
+        }
+        ServicesCommand {
+            command:
+                Pause {
+                    cluster,
+                    region,
+                    state,
+                    sleep,
+                },
+        } => {
+            let path = state.unwrap_or_else(|| pause::default_path(&cluster, region.name()));
+            let ecs_client = helpers::ecs_client(args.profile, region.clone())?;
+            let services = services::describe_services(&ecs_client, cluster.clone())?;
+
+            let state = pause::PauseState {
+                cluster: cluster.clone(),
+                region: region.name().to_string(),
+                paused_at: helpers::today(),
+                desired_counts: services
+                    .iter()
+                    .map(|s| {
+                        Ok((
+                            services::service_name(s)?,
+                            s.desired_count.ok_or(format_err!(
+                                "Service {} has no desired count",
+                                services::service_name(s)?
+                            ))?,
+                        ))
+                    })
+                    .collect::<Result<_, Error>>()?,
+            };
+            state.save(&path)?;
+            println!("Recorded desired counts in {}", path.display());
+
+            for service in services {
+                if service.desired_count == Some(0) {
+                    continue;
+                }
+
+                services::update_service(
+                    &ecs_client,
+                    cluster.clone(),
+                    service,
+                    ServiceModification::DesiredCount {
+                        count: DesiredCountOptions::Number(0),
+                        min: None,
+                        max: None,
+                    },
+                )?;
+
+                thread::sleep(Duration::from_millis(sleep));
+            }
+        }
+        ServicesCommand {
+            command:
+                Resume {
+                    cluster,
+                    region,
+                    state,
+                    sleep,
+                },
+        } => {
+            let path = state.unwrap_or_else(|| pause::default_path(&cluster, region.name()));
+            let state = pause::PauseState::load(&path, &cluster, region.name())?;
             let ecs_client = helpers::ecs_client(args.profile, region)?;
+
+            let mut restored = Vec::new();
             for service in services::describe_services(&ecs_client, cluster.clone())? {
                 let service_name = services::service_name(&service)?;
+                let count = match state.desired_counts.get(&service_name) {
+                    Some(&count) => count,
+                    None => {
+                        warn!(
+                            "{} was not recorded when pausing, leaving it alone",
+                            service_name
+                        );
+                        continue;
+                    }
+                };
+                restored.push(service_name);
 
-                println!(
-                    "{}/{} - Task: {} - Desired Count: {}",
-                    cluster,
-                    service_name,
-                    service.task_definition.ok_or(format_err!(
-                        "Service {:?} has no task definition",
-                        &service_name
-                    ))?,
-                    service
-                        .desired_count
-                        .ok_or(format_err!("Service {} has no desired count", service_name))?,
-                );
+                if service.desired_count == Some(count) {
+                    continue;
+                }
+
+                services::update_service(
+                    &ecs_client,
+                    cluster.clone(),
+                    service,
+                    ServiceModification::DesiredCount {
+                        count: DesiredCountOptions::Number(count),
+                        min: None,
+                        max: None,
+                    },
+                )?;
+
+                thread::sleep(Duration::from_millis(sleep));
             }
+
+            for service_name in state.desired_counts.keys() {
+                if !restored.contains(service_name) {
+                    warn!("{} was paused, but no longer exists", service_name);
+                }
+            }
+
+            pause::PauseState::remove(&path)?;
+            println!("Resumed {}, removed {}", cluster, path.display());
         }
         ServicesCommand {
-            command: Audit { cluster, region },
+            command:
+                StoppedTasks {
+                    cluster,
+                    region,
+                    service,
+                },
         } => {
-            let ecs_client = helpers::ecs_client(args.profile.clone(), region.clone())?;
-            let ecr_client = helpers::ecr_client(args.profile.clone(), region.clone())?;
-            let elb_client = helpers::elb_client(args.profile, region)?;
-            for service in services::describe_services(&ecs_client, cluster)? {
-                let service_name = services::service_name(&service)?;
+            let ecs_client = helpers::ecs_client(args.profile, region)?;
 
-                let audit_message =
-                    services::audit_service(&ecs_client, &ecr_client, &elb_client, &service)?
-                        .join(", ");
+            let task_arns =
+                tasks::list_tasks(&ecs_client, cluster.clone(), Some(service), None, "STOPPED")?;
+            let stopped = tasks::describe_tasks(&ecs_client, cluster.clone(), &task_arns)?;
 
-                if !audit_message.is_empty() {
-                    println!("{} [{}]", service_name, audit_message);
+            for task in &stopped {
+                println!("{}/{}", cluster, tasks::task_summary(task));
+            }
+
+            for (title, histogram) in tasks::stopped_task_histograms(&stopped) {
+                println!();
+                println!("{}:", title);
+                for (value, count) in histogram {
+                    println!("  {}: {}", value, count);
                 }
             }
+
+            println!();
+            println!("Total: {}", stopped.len());
         }
         ServicesCommand {
             command:
@@ -108,7 +331,7 @@ fn main() -> Result<(), Error> {
 
             println!("Not in destination:");
             for service in &source_only_services {
-                println!("{}/{}", source_cluster, services::service_name(&service)?);
+                println!("{}/{}", source_cluster, services::service_name(service)?);
             }
 
             println!("Total: {}", source_only_services.len());
@@ -125,25 +348,24 @@ fn main() -> Result<(), Error> {
         } => {
             let destination_ecs_client =
                 helpers::ecs_client(args.profile.clone(), destination_region)?;
-            let source_ecs_client =
-                helpers::ecs_client(args.profile.clone(), source_region.clone())?;
-            let source_ecr_client =
-                helpers::ecr_client(args.profile.clone(), source_region.clone())?;
-            let source_elb_client = helpers::elb_client(args.profile, source_region.clone())?;
+            let source_ctx = audit::AuditContext::new(args.profile, source_region)?;
+            let checks = audit::sync_checks();
             let source_only_services = services::compare_services(
-                &source_ecs_client,
+                &source_ctx.ecs_client,
                 source_cluster.clone(),
                 &destination_ecs_client,
                 destination_cluster.clone(),
             )?;
 
             for source_service in source_only_services {
-                if services::audit_service(
-                    &source_ecs_client,
-                    &source_ecr_client,
-                    &source_elb_client,
+                if audit::audit_service(
+                    &source_ctx,
+                    &checks,
+                    &audit::Suppressions::default(),
+                    &source_cluster,
                     &source_service,
                 )?
+                .findings
                 .is_empty()
                 {
                     thread::sleep(Duration::from_millis(10000));
@@ -158,42 +380,97 @@ fn main() -> Result<(), Error> {
             }
         }
         ServicesCommand {
-            command:
-                Export {
-                    cluster,
-                    region,
-                    property,
-                },
+            command: Export { targets, property },
         } => {
-            let ecs_client = helpers::ecs_client(args.profile, region)?;
+            let targets = clusters::resolve_targets(args.profile.clone(), &targets)?;
+            let fleet = helpers::is_fleet(clusters::target_clusters(&targets));
 
-            let service_properties = services::describe_services(&ecs_client, cluster.clone())?
-                .into_iter()
-                .map(|s| {
-                    let property_value = match property {
-                        ServiceProperty::DesiredCount => s.desired_count,
-                    };
-
-                    Ok((
-                        services::service_name(&s)?,
-                        Number(JsonNumber::from(property_value.unwrap())),
-                    ))
-                })
-                .collect::<Result<HashMap<String, Value>, Error>>()?;
+            let mut regions = serde_json::Map::new();
+            for target in targets {
+                let ecs_client = helpers::ecs_client(args.profile.clone(), target.region.clone())?;
+
+                let mut clusters = serde_json::Map::new();
+                for cluster in target.clusters {
+                    let service_properties =
+                        services::describe_services(&ecs_client, cluster.clone())?
+                            .into_iter()
+                            .map(|s| {
+                                let property_value = match property {
+                                    ServiceProperty::DesiredCount => s.desired_count,
+                                };
+
+                                Ok((
+                                    services::service_name(&s)?,
+                                    Number(JsonNumber::from(property_value.unwrap())),
+                                ))
+                            })
+                            .collect::<Result<serde_json::Map<String, Value>, Error>>()?;
 
-            println!("{}", serde_json::to_string_pretty(&service_properties)?);
+                    if !fleet {
+                        println!("{}", serde_json::to_string_pretty(&service_properties)?);
+                        return Ok(());
+                    }
+
+                    clusters.insert(cluster, Value::Object(service_properties));
+                }
+
+                regions.insert(target.region.name().to_string(), Value::Object(clusters));
+            }
+
+            // Several clusters are nested by region and then cluster, so `update` takes one
+            // cluster's object at a time
+            println!("{}", serde_json::to_string_pretty(&regions)?);
         }
         ServicesCommand {
             command:
                 Update {
                     cluster,
                     region,
-                    modification,
+                    mut modification,
                     sleep,
+                    strict,
+                    format,
                 },
         } => {
+            modification.validate()?;
+            modification.resolve_map(format, region.name(), &cluster)?;
+
             let ecs_client = helpers::ecs_client(args.profile, region)?;
-            for service in services::describe_services(&ecs_client, cluster.clone())? {
+            let services = services::describe_services(&ecs_client, cluster.clone())?;
+
+            if let Some(mut mapped) = modification.mapped_services() {
+                mapped.sort();
+                let service_names = services
+                    .iter()
+                    .map(services::service_name)
+                    .collect::<Result<Vec<String>, Error>>()?;
+
+                let unmapped = service_names
+                    .iter()
+                    .filter(|name| !mapped.contains(name))
+                    .cloned()
+                    .collect::<Vec<String>>();
+                let unknown = mapped
+                    .iter()
+                    .filter(|name| !service_names.contains(name))
+                    .map(|name| name.to_string())
+                    .collect::<Vec<String>>();
+
+                if !unmapped.is_empty() {
+                    println!(
+                        "Not in the map, will be left alone: {}",
+                        unmapped.join(", ")
+                    );
+                }
+                if !unknown.is_empty() {
+                    println!("In the map, but not in {}: {}", cluster, unknown.join(", "));
+                }
+                if strict && !(unmapped.is_empty() && unknown.is_empty()) {
+                    bail!("The map and {} don't match, not changing anything", cluster);
+                }
+            }
+
+            for service in services {
                 services::update_service(
                     &ecs_client,
                     cluster.clone(),
@@ -204,6 +481,459 @@ fn main() -> Result<(), Error> {
                 thread::sleep(Duration::from_millis(sleep));
             }
         }
+        ServicesCommand {
+            command:
+                Apply {
+                    manifest,
+                    format,
+                    prune,
+                    protected,
+                    drain_timeout,
+                    dry_run,
+                    yes,
+                    sleep,
+                },
+        } => {
+            let protected = protected_services(protected)?;
+            let manifest = manifest::load(&manifest, format)?;
+            let ecs_client = helpers::ecs_client(args.profile, manifest.region.parse()?)?;
+            let services = services::describe_services(&ecs_client, manifest.cluster.clone())?;
+            let plan = manifest::plan(&ecs_client, &manifest, &services, prune)?;
+            refuse_protected(&plan.deletions, &protected)?;
+
+            for change in &plan.changes {
+                println!("{}", change);
+            }
+            for service_name in &plan.deletions {
+                println!("- {}", service_name);
+            }
+            for (service, differences) in &plan.fixed {
+                println!(
+                    "! {} differs in settings that can only be chosen when creating it:",
+                    service
+                );
+                for difference in differences {
+                    println!("    {}", difference);
+                }
+            }
+            if !plan.unmanaged.is_empty() {
+                println!(
+                    "Not in the manifest, will be left alone: {}",
+                    plan.unmanaged.join(", ")
+                );
+            }
+
+            let total = plan.changes.len() + plan.deletions.len();
+            println!("Total: {}", total);
+
+            // Pruning asks for the cluster name to be typed, like deleting services does
+            if !dry_run
+                && total > 0
+                && (yes
+                    || if plan.deletions.is_empty() {
+                        helpers::confirm(&format!(
+                            "Apply {} changes to {}?",
+                            total, manifest.cluster
+                        ))?
+                    } else {
+                        helpers::confirm_typed(
+                            &format!(
+                                "Type the cluster name to apply {} changes, deleting {} services:",
+                                total,
+                                plan.deletions.len()
+                            ),
+                            &manifest.cluster,
+                        )?
+                    })
+            {
+                for change in plan.changes {
+                    manifest::apply(&ecs_client, &manifest.cluster, change)?;
+
+                    thread::sleep(Duration::from_millis(sleep));
+                }
+
+                services::delete_services(
+                    &ecs_client,
+                    &manifest.cluster,
+                    &plan.deletions,
+                    Duration::from_secs(drain_timeout * 60),
+                    Duration::from_millis(sleep),
+                )?;
+            }
+        }
+        ServicesCommand {
+            command:
+                Delete {
+                    cluster,
+                    region,
+                    services: patterns,
+                    protected,
+                    drain_timeout,
+                    sleep,
+                    dry_run,
+                    yes,
+                },
+        } => {
+            let protected = protected_services(protected)?;
+
+            let ecs_client = helpers::ecs_client(args.profile, region)?;
+            let service_names = services::list_services(&ecs_client, cluster.clone())?
+                .iter()
+                .filter_map(|arn| arn.rsplit('/').next())
+                .map(String::from)
+                .collect::<Vec<String>>();
+
+            for pattern in &patterns {
+                if !service_names
+                    .iter()
+                    .any(|name| helpers::glob_match(pattern, name))
+                {
+                    warn!("No services in {} match {}", cluster, pattern);
+                }
+            }
+
+            let mut selected = service_names
+                .into_iter()
+                .filter(|name| {
+                    patterns
+                        .iter()
+                        .any(|pattern| helpers::glob_match(pattern, name))
+                })
+                .collect::<Vec<String>>();
+            selected.sort();
+
+            refuse_protected(&selected, &protected)?;
+
+            for service_name in &selected {
+                println!("{}/{}", cluster, service_name);
+            }
+
+            println!("Total: {}", selected.len());
+
+            if !dry_run
+                && !selected.is_empty()
+                && (yes
+                    || helpers::confirm_typed(
+                        &format!(
+                            "Type the cluster name to delete {} services from it:",
+                            selected.len()
+                        ),
+                        &cluster,
+                    )?)
+            {
+                services::delete_services(
+                    &ecs_client,
+                    &cluster,
+                    &selected,
+                    Duration::from_secs(drain_timeout * 60),
+                    Duration::from_millis(sleep),
+                )?;
+            }
+        }
+        ServicesCommand {
+            command:
+                DumpManifest {
+                    cluster,
+                    region,
+                    format,
+                    references,
+                    desired_counts,
+                },
+        } => {
+            let ecs_client = helpers::ecs_client(args.profile, region.clone())?;
+            let services = services::describe_services(&ecs_client, cluster.clone())?;
+            let manifest = manifest::dump(
+                &ecs_client,
+                &cluster,
+                region.name(),
+                &services,
+                references,
+                desired_counts,
+            )?;
+
+            println!("{}", manifest::render(&manifest, format)?.trim_end());
+        }
+        ClustersCommand {
+            command: ListClusters { region },
+        } => {
+            for region in region.0 {
+                let ecs_client = helpers::ecs_client(args.profile.clone(), region.clone())?;
+                for cluster in clusters::list_clusters(&ecs_client)? {
+                    println!("{}/{}", region.name(), cluster);
+                }
+            }
+        }
+        ClustersCommand {
+            command: ClusterInfo { targets },
+        } => {
+            for target in clusters::resolve_targets(args.profile.clone(), &targets)? {
+                let ecs_client = helpers::ecs_client(args.profile.clone(), target.region.clone())?;
+                for cluster in target.clusters {
+                    let summary = clusters::cluster_summary(&ecs_client, cluster.clone())?;
+
+                    println!("{}/{}", target.region.name(), cluster);
+                    for (label, value) in summary.properties() {
+                        println!("  {}: {}", label, value);
+                    }
+                }
+            }
+        }
+        ClustersCommand {
+            command:
+                CompareClusters {
+                    source_cluster,
+                    source_region,
+                    destination_cluster,
+                    destination_region,
+                },
+        } => {
+            let source = clusters::cluster_summary(
+                &helpers::ecs_client(args.profile.clone(), source_region)?,
+                source_cluster.clone(),
+            )?;
+            let destination = clusters::cluster_summary(
+                &helpers::ecs_client(args.profile.clone(), destination_region)?,
+                destination_cluster.clone(),
+            )?;
+
+            let differences = source
+                .settings()
+                .into_iter()
+                .zip(destination.settings())
+                .filter(|&((_, ref s), (_, ref d))| s != d)
+                .collect::<Vec<_>>();
+
+            for ((label, source_value), (_, destination_value)) in &differences {
+                println!("{}:", label);
+                println!("  {}: {}", source_cluster, source_value);
+                println!("  {}: {}", destination_cluster, destination_value);
+            }
+
+            println!("Total: {}", differences.len());
+
+            println!();
+            println!("For information, not compared:");
+            for ((label, source_value), (_, destination_value)) in
+                source.counters().into_iter().zip(destination.counters())
+            {
+                println!("  {}: {} / {}", label, source_value, destination_value);
+            }
+        }
+        TasksCommand {
+            command:
+                ListTasks {
+                    cluster,
+                    region,
+                    service,
+                    stopped,
+                },
+        } => {
+            let ecs_client = helpers::ecs_client(args.profile, region)?;
+            let desired_status = if stopped { "STOPPED" } else { "RUNNING" };
+
+            let task_arns =
+                tasks::list_tasks(&ecs_client, cluster.clone(), service, None, desired_status)?;
+            for task in tasks::describe_tasks(&ecs_client, cluster.clone(), &task_arns)? {
+                println!("{}/{}", cluster, tasks::task_summary(&task));
+            }
+        }
+        TasksCommand {
+            command:
+                StopTasks {
+                    cluster,
+                    region,
+                    filter,
+                    reason,
+                    sleep,
+                    dry_run,
+                    yes,
+                },
+        } => {
+            if filter.is_empty() {
+                bail!(
+                    "Refusing to stop every task in {}, pass at least one filter",
+                    cluster
+                );
+            }
+
+            let ecs_client = helpers::ecs_client(args.profile, region)?;
+            let matching = tasks::filter_tasks(&ecs_client, cluster.clone(), &filter)?;
+
+            for task in &matching {
+                println!("{}/{}", cluster, tasks::task_summary(task));
+            }
+
+            println!("Total: {}", matching.len());
+
+            if !dry_run
+                && !matching.is_empty()
+                && (yes || helpers::confirm(&format!("Stop {} tasks?", matching.len()))?)
+            {
+                for task in &matching {
+                    eprintln!("Stopping {}/{}", cluster, tasks::task_summary(task));
+                    tasks::stop_task(&ecs_client, cluster.clone(), task, reason.clone())?;
+
+                    thread::sleep(Duration::from_millis(sleep));
+                }
+            }
+        }
+        TaskDefinitionsCommand {
+            command: ListTaskDefinitions { targets },
+        } => {
+            for target in clusters::resolve_targets(args.profile.clone(), &targets)? {
+                let ecs_client = helpers::ecs_client(args.profile.clone(), target.region.clone())?;
+                let in_use = task_definitions_in_use(&ecs_client, &target.clusters)?;
+
+                for family in task_definitions::family_revisions(&ecs_client, &in_use)? {
+                    let unused = family
+                        .unused(0)
+                        .iter()
+                        .map(|&&(revision, _)| revision.to_string())
+                        .collect::<Vec<String>>();
+                    let in_use = family
+                        .in_use
+                        .iter()
+                        .map(|revision| revision.to_string())
+                        .collect::<Vec<String>>();
+
+                    println!(
+                        "{}/{} - In use: {} - Unused: {}",
+                        target.region.name(),
+                        family.family,
+                        in_use.join(", "),
+                        unused.join(", ")
+                    );
+                }
+            }
+        }
+        TaskDefinitionsCommand {
+            command: DiffTaskDefinitions { region, a, b },
+        } => {
+            let ecs_client = helpers::ecs_client(args.profile, region)?;
+            let a = task_definitions::describe_task_definition(&ecs_client, a)?;
+            let b = task_definitions::describe_task_definition(&ecs_client, b)?;
+
+            for (section, changes) in task_definitions::diff(&a, &b)? {
+                println!("{}:", section);
+                for change in changes {
+                    println!("  {}", change);
+                }
+            }
+        }
+        TaskDefinitionsCommand {
+            command:
+                DeregisterUnused {
+                    targets,
+                    keep,
+                    only_given_clusters,
+                    dry_run,
+                    yes,
+                },
+        } => {
+            warn!(
+                "Only revisions used by services and running tasks are kept, make sure none are \
+                 needed by scheduled tasks or tasks started outside of the clusters' services"
+            );
+
+            let mut unused = Vec::new();
+            for target in clusters::resolve_targets(args.profile.clone(), &targets)? {
+                let ecs_client = helpers::ecs_client(args.profile.clone(), target.region.clone())?;
+                let clusters = if only_given_clusters {
+                    target.clusters.clone()
+                } else {
+                    clusters::list_clusters(&ecs_client)?
+                };
+                let in_use = task_definitions_in_use(&ecs_client, &clusters)?;
+
+                for family in task_definitions::family_revisions(&ecs_client, &in_use)? {
+                    for (_, arn) in family.unused(keep) {
+                        println!("{}", arn);
+                        unused.push((target.region.clone(), arn.clone()));
+                    }
+                }
+            }
+
+            println!("Total: {}", unused.len());
+
+            if !dry_run
+                && !unused.is_empty()
+                && (yes || helpers::confirm(&format!("Deregister {} revisions?", unused.len()))?)
+            {
+                for (region, arn) in unused {
+                    let ecs_client = helpers::ecs_client(args.profile.clone(), region)?;
+                    task_definitions::deregister_task_definition(&ecs_client, arn)?;
+                }
+            }
+        }
+        TaskDefinitionsCommand {
+            command:
+                BumpImage {
+                    region,
+                    task_definition,
+                    image,
+                    container,
+                },
+        } => {
+            let ecs_client = helpers::ecs_client(args.profile, region)?;
+            let task_definition =
+                task_definitions::describe_task_definition(&ecs_client, task_definition)?;
+
+            let req = task_definitions::with_image(&task_definition, container.as_deref(), &image)?;
+            let registered = task_definitions::register_task_definition(&ecs_client, req)?;
+
+            println!("{}", registered.task_definition_arn.unwrap_or_default());
+        }
+    }
+
+    Ok(())
+}
+
+/// The task definitions deployed by the services of some clusters or run by their tasks,
+/// including tasks started without a service
+fn task_definitions_in_use(
+    ecs_client: &rusoto_ecs::EcsClient,
+    clusters: &[String],
+) -> Result<HashSet<String>, Error> {
+    let mut in_use = HashSet::new();
+    for cluster in clusters {
+        let services = services::describe_services(ecs_client, cluster.clone())?;
+        in_use.extend(task_definitions::task_definitions_in_use(&services));
+
+        let task_arns = tasks::list_tasks(ecs_client, cluster.clone(), None, None, "RUNNING")?;
+        in_use.extend(
+            tasks::describe_tasks(ecs_client, cluster.clone(), &task_arns)?
+                .into_iter()
+                .filter_map(|task| task.task_definition_arn),
+        );
+    }
+
+    Ok(in_use)
+}
+
+/// The service name patterns in a `--protected` list, if one was given
+fn protected_services(source: Option<String>) -> Result<Vec<String>, Error> {
+    match source {
+        Some(source) => input::parse::<Vec<String>>(&source, &input::read_source(&source)?, None),
+        None => Ok(Vec::new()),
+    }
+}
+
+/// Fails when any of the services about to be deleted is protected
+fn refuse_protected(service_names: &[String], protected: &[String]) -> Result<(), Error> {
+    let refused = service_names
+        .iter()
+        .filter(|name| {
+            protected
+                .iter()
+                .any(|pattern| helpers::glob_match(pattern, name))
+        })
+        .cloned()
+        .collect::<Vec<String>>();
+    if !refused.is_empty() {
+        bail!(
+            "Refusing to delete protected services: {}",
+            refused.join(", ")
+        );
     }
 
     Ok(())
@@ -0,0 +1,873 @@
+use failure::Error;
+use rusoto_ecs::{
+    ContainerDefinition, CreateServiceRequest, DeploymentConfiguration, EcsClient, LoadBalancer,
+    NetworkConfiguration, PlacementConstraint, PlacementStrategy, RegisterTaskDefinitionRequest,
+    Service, ServiceRegistry, TaskDefinitionPlacementConstraint, UpdateServiceRequest, Volume,
+};
+use serde::de::value::MapAccessDeserializer;
+use serde::de::{self, Deserializer, MapAccess, Visitor};
+use serde::Deserialize;
+use serde_json::{self, Map, Value};
+use serde_yaml;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::mem;
+use toml;
+
+use input::{self, InputFormat};
+use services;
+use task_definitions;
+
+/// The services a cluster should run, as `services apply` reads it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct Manifest {
+    pub cluster: String,
+    pub region: String,
+    #[serde(default)]
+    pub services: BTreeMap<String, ServiceSpec>,
+}
+
+/// A service as a manifest declares it. Optional settings that are left out are not managed:
+/// updates leave them as they are, and new services get the ECS defaults
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct ServiceSpec {
+    pub task_definition: TaskDefinitionSpec,
+    /// Left out for services whose count auto scaling manages. New services then start with
+    /// no tasks
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub desired_count: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deployment_configuration: Option<DeploymentConfiguration>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub network_configuration: Option<NetworkConfiguration>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub platform_version: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub health_check_grace_period_seconds: Option<i64>,
+    // The settings below can only be chosen when a service is created
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub launch_type: Option<String>,
+    /// The service role, as a name or an ARN
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub role: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub load_balancers: Option<Vec<LoadBalancer>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub service_registries: Option<Vec<ServiceRegistry>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub placement_constraints: Option<Vec<PlacementConstraint>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub placement_strategy: Option<Vec<PlacementStrategy>>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(untagged)]
+pub enum TaskDefinitionSpec {
+    /// A registered task definition, as `family` for any revision of it, `family:revision`
+    /// or an ARN
+    Reference(String),
+    /// A task definition, registered as a new revision whenever the service's current
+    /// revision differs from it
+    Definition(Box<TaskDefinitionBody>),
+}
+
+// Picks the variant by the shape of the value rather than trying each in turn, so a mistake
+// in an inline task definition is reported as such instead of as matching no variant
+impl<'de> Deserialize<'de> for TaskDefinitionSpec {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct TaskDefinitionVisitor;
+
+        impl<'de> Visitor<'de> for TaskDefinitionVisitor {
+            type Value = TaskDefinitionSpec;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a task definition reference or a task definition")
+            }
+
+            fn visit_str<E: de::Error>(self, reference: &str) -> Result<Self::Value, E> {
+                Ok(TaskDefinitionSpec::Reference(reference.to_string()))
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+                let body = TaskDefinitionBody::deserialize(MapAccessDeserializer::new(map))?;
+                Ok(TaskDefinitionSpec::Definition(Box::new(body)))
+            }
+        }
+
+        deserializer.deserialize_any(TaskDefinitionVisitor)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct TaskDefinitionBody {
+    pub family: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub task_role_arn: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub execution_role_arn: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub network_mode: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub requires_compatibilities: Option<Vec<String>>,
+    /// Task-level CPU units, as a string like ECS returns them, e.g. "256"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cpu: Option<String>,
+    /// Task-level memory in MiB, as a string like ECS returns it, e.g. "512"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub memory: Option<String>,
+    pub container_definitions: Vec<ContainerDefinition>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub volumes: Option<Vec<Volume>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub placement_constraints: Option<Vec<TaskDefinitionPlacementConstraint>>,
+}
+
+impl From<RegisterTaskDefinitionRequest> for TaskDefinitionBody {
+    fn from(req: RegisterTaskDefinitionRequest) -> Self {
+        TaskDefinitionBody {
+            family: req.family,
+            task_role_arn: req.task_role_arn,
+            execution_role_arn: req.execution_role_arn,
+            network_mode: req.network_mode,
+            requires_compatibilities: req.requires_compatibilities,
+            cpu: req.cpu,
+            memory: req.memory,
+            container_definitions: req.container_definitions,
+            volumes: req.volumes,
+            placement_constraints: req.placement_constraints,
+        }
+    }
+}
+
+impl TaskDefinitionBody {
+    pub fn register_request(&self) -> RegisterTaskDefinitionRequest {
+        RegisterTaskDefinitionRequest {
+            container_definitions: self.container_definitions.clone(),
+            cpu: self.cpu.clone(),
+            execution_role_arn: self.execution_role_arn.clone(),
+            family: self.family.clone(),
+            memory: self.memory.clone(),
+            network_mode: self.network_mode.clone(),
+            placement_constraints: self.placement_constraints.clone(),
+            requires_compatibilities: self.requires_compatibilities.clone(),
+            task_role_arn: self.task_role_arn.clone(),
+            volumes: self.volumes.clone(),
+        }
+    }
+}
+
+/// Reads a manifest from a file, or from stdin for `-`
+pub fn load(source: &str, format: Option<InputFormat>) -> Result<Manifest, Error> {
+    input::parse(source, &input::read_source(source)?, format)
+}
+
+#[derive(Debug)]
+pub enum Action {
+    Create(CreateServiceRequest),
+    Update(UpdateServiceRequest),
+}
+
+/// A change `apply` makes to one service
+#[derive(Debug)]
+pub struct Change {
+    pub service: String,
+    pub action: Action,
+    /// A task definition to register first. The service is created or updated with the new
+    /// revision
+    pub register: Option<RegisterTaskDefinitionRequest>,
+    /// What changes, for the plan
+    pub details: Vec<String>,
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let sign = match self.action {
+            Action::Create(_) => "+",
+            Action::Update(_) => "~",
+        };
+        write!(f, "{} {}", sign, self.service)?;
+        for detail in &self.details {
+            write!(f, "\n    {}", detail)?;
+        }
+        Ok(())
+    }
+}
+
+/// What it takes to make a cluster match a manifest
+#[derive(Debug)]
+pub struct Plan {
+    pub changes: Vec<Change>,
+    /// Services in the cluster that the manifest doesn't declare, to delete when pruning.
+    /// They are deleted like `services delete` does, after the other changes
+    pub deletions: Vec<String>,
+    /// Differences in settings ECS can only choose when creating a service, by service.
+    /// Changing them takes deleting and recreating the service, which `apply` doesn't do
+    pub fixed: Vec<(String, Vec<String>)>,
+    /// Services in the cluster that the manifest doesn't declare, when they aren't pruned
+    pub unmanaged: Vec<String>,
+}
+
+/// Compares the manifest to the cluster's services. Services the manifest doesn't declare
+/// are only deleted when `prune` is set
+pub fn plan(
+    ecs_client: &EcsClient,
+    manifest: &Manifest,
+    services: &[Service],
+    prune: bool,
+) -> Result<Plan, Error> {
+    let current = services
+        .iter()
+        .map(|service| Ok((services::service_name(service)?, service)))
+        .collect::<Result<HashMap<String, &Service>, Error>>()?;
+
+    let mut plan = Plan {
+        changes: Vec::new(),
+        deletions: Vec::new(),
+        fixed: Vec::new(),
+        unmanaged: Vec::new(),
+    };
+
+    for (name, spec) in &manifest.services {
+        match current.get(name) {
+            None => plan
+                .changes
+                .push(plan_create(&manifest.cluster, name, spec)),
+            Some(service) => {
+                let (change, fixed) = plan_update(ecs_client, &manifest.cluster, spec, service)?;
+                if let Some(change) = change {
+                    plan.changes.push(change);
+                }
+                if !fixed.is_empty() {
+                    plan.fixed.push((name.clone(), fixed));
+                }
+            }
+        }
+    }
+
+    let mut undeclared = current
+        .keys()
+        .filter(|name| !manifest.services.contains_key(*name))
+        .cloned()
+        .collect::<Vec<String>>();
+    undeclared.sort();
+
+    for name in undeclared {
+        if prune {
+            plan.deletions.push(name);
+        } else {
+            plan.unmanaged.push(name);
+        }
+    }
+
+    Ok(plan)
+}
+
+fn plan_create(cluster: &str, name: &str, spec: &ServiceSpec) -> Change {
+    let (task_definition, register) = match spec.task_definition {
+        TaskDefinitionSpec::Reference(ref reference) => (reference.clone(), None),
+        TaskDefinitionSpec::Definition(ref body) => {
+            (body.family.clone(), Some(body.register_request()))
+        }
+    };
+
+    let desired_count = spec.desired_count.unwrap_or(0);
+
+    Change {
+        service: name.to_string(),
+        details: vec![
+            format!("taskDefinition: {}", task_definition),
+            format!("desiredCount: {}", desired_count),
+        ],
+        action: Action::Create(CreateServiceRequest {
+            client_token: None,
+            cluster: Some(cluster.to_string()),
+            deployment_configuration: spec.deployment_configuration.clone(),
+            desired_count,
+            health_check_grace_period_seconds: spec.health_check_grace_period_seconds,
+            launch_type: spec.launch_type.clone(),
+            load_balancers: spec.load_balancers.clone(),
+            network_configuration: spec.network_configuration.clone(),
+            placement_constraints: spec.placement_constraints.clone(),
+            placement_strategy: spec.placement_strategy.clone(),
+            platform_version: spec.platform_version.clone(),
+            role: spec.role.clone(),
+            service_name: name.to_string(),
+            task_definition,
+            service_registries: spec.service_registries.clone(),
+        }),
+        register,
+    }
+}
+
+/// The change that brings a service in line with its spec, if any, and the differences that
+/// can't be changed in place
+fn plan_update(
+    ecs_client: &EcsClient,
+    cluster: &str,
+    spec: &ServiceSpec,
+    service: &Service,
+) -> Result<(Option<Change>, Vec<String>), Error> {
+    let name = services::service_name(service)?;
+    let current_arn = service
+        .task_definition
+        .clone()
+        .ok_or(format_err!("Service {} has no task definition", name))?;
+
+    let mut req = services::update_service_template(cluster.to_string(), name.clone());
+    let mut register = None;
+    let mut details = Vec::new();
+    let mut fixed = Vec::new();
+
+    match spec.task_definition {
+        TaskDefinitionSpec::Reference(ref reference) => {
+            if !refers_to(&current_arn, reference) {
+                details.push(format!("taskDefinition: {} -> {}", current_arn, reference));
+                req.task_definition = Some(reference.clone());
+            }
+        }
+        TaskDefinitionSpec::Definition(ref body) => {
+            let mut current = task_definitions::register_request(
+                &task_definitions::describe_task_definition(ecs_client, current_arn.clone())?,
+            )?;
+            let mut desired = body.register_request();
+            task_definitions::strip_defaults(&mut current);
+            task_definitions::strip_defaults(&mut desired);
+
+            let sections = task_definitions::diff_requests(&current, &desired)?;
+            if !sections.is_empty() {
+                for (section, changes) in sections {
+                    for change in changes {
+                        details.push(format!("taskDefinition {}: {}", section, change));
+                    }
+                }
+                register = Some(body.register_request());
+            }
+        }
+    }
+
+    let (mut current, mut desired) = (current_spec(service, &current_arn, spec), spec.clone());
+    normalize(&mut current);
+    normalize(&mut desired);
+    let current = serde_json::to_value(&current)?;
+
+    if let Value::Object(desired) = serde_json::to_value(&desired)? {
+        for (key, desired_value) in desired {
+            if key == "taskDefinition" {
+                continue;
+            }
+
+            let (mut a, mut b) = (BTreeMap::new(), BTreeMap::new());
+            task_definitions::flatten(&key, current.get(&key).unwrap_or(&Value::Null), &mut a);
+            task_definitions::flatten(&key, &desired_value, &mut b);
+            let changes = task_definitions::diff_flattened(&a, &b);
+            if changes.is_empty() {
+                continue;
+            }
+
+            match key.as_str() {
+                "desiredCount" => req.desired_count = spec.desired_count,
+                "deploymentConfiguration" => {
+                    req.deployment_configuration = spec.deployment_configuration.clone()
+                }
+                "networkConfiguration" => {
+                    req.network_configuration = spec.network_configuration.clone()
+                }
+                "platformVersion" => req.platform_version = spec.platform_version.clone(),
+                "healthCheckGracePeriodSeconds" => {
+                    req.health_check_grace_period_seconds = spec.health_check_grace_period_seconds
+                }
+                _ => {
+                    fixed.extend(changes);
+                    continue;
+                }
+            }
+            details.extend(changes);
+        }
+    }
+
+    let change = if details.is_empty() {
+        None
+    } else {
+        Some(Change {
+            service: name,
+            action: Action::Update(req),
+            register,
+            details,
+        })
+    };
+
+    Ok((change, fixed))
+}
+
+/// Whether a task definition ARN is the one a manifest refers to
+fn refers_to(arn: &str, reference: &str) -> bool {
+    if reference.starts_with("arn:") {
+        return arn == reference;
+    }
+
+    match task_definitions::task_definition_family_revision(arn) {
+        Some((family, revision)) if reference.contains(':') => {
+            reference == format!("{}:{}", family, revision)
+        }
+        Some((family, _)) => reference == family,
+        None => false,
+    }
+}
+
+/// A service's settings in the shape of a spec
+fn service_spec(service: &Service, task_definition: TaskDefinitionSpec) -> ServiceSpec {
+    ServiceSpec {
+        task_definition,
+        desired_count: service.desired_count,
+        deployment_configuration: service.deployment_configuration.clone(),
+        network_configuration: service.network_configuration.clone(),
+        platform_version: service.platform_version.clone(),
+        health_check_grace_period_seconds: service.health_check_grace_period_seconds,
+        launch_type: service.launch_type.clone(),
+        role: service.role_arn.clone(),
+        load_balancers: service.load_balancers.clone(),
+        service_registries: service.service_registries.clone(),
+        placement_constraints: service.placement_constraints.clone(),
+        placement_strategy: service.placement_strategy.clone(),
+    }
+}
+
+/// A service's current settings in the shape of its spec, with the role written the same way
+fn current_spec(service: &Service, current_arn: &str, spec: &ServiceSpec) -> ServiceSpec {
+    let mut current = service_spec(
+        service,
+        TaskDefinitionSpec::Reference(current_arn.to_string()),
+    );
+
+    if spec
+        .role
+        .as_ref()
+        .is_some_and(|role| !role.starts_with("arn:"))
+    {
+        current.role = current
+            .role
+            .as_ref()
+            .and_then(|arn| arn.rsplit('/').next())
+            .map(String::from);
+    }
+
+    current
+}
+
+/// Sorts subnets and security groups, and clears a public IP assignment that is disabled,
+/// the default, so equivalent network configurations compare equal
+fn normalize(spec: &mut ServiceSpec) {
+    let awsvpc = spec
+        .network_configuration
+        .as_mut()
+        .and_then(|n| n.awsvpc_configuration.as_mut());
+
+    if let Some(awsvpc) = awsvpc {
+        awsvpc.subnets.sort();
+        if let Some(ref mut security_groups) = awsvpc.security_groups {
+            security_groups.sort();
+        }
+        if awsvpc.assign_public_ip.as_deref() == Some("DISABLED") {
+            awsvpc.assign_public_ip = None;
+        }
+    }
+}
+
+/// A manifest for the services of a cluster as they are, leaving out settings that have
+/// their default values. Task definitions are inlined, or referred to as `family:revision`
+/// when `references` is set. Desired counts are only included when `desired_counts` is set
+pub fn dump(
+    ecs_client: &EcsClient,
+    cluster: &str,
+    region: &str,
+    services: &[Service],
+    references: bool,
+    desired_counts: bool,
+) -> Result<Manifest, Error> {
+    let mut manifest = Manifest {
+        cluster: cluster.to_string(),
+        region: region.to_string(),
+        services: BTreeMap::new(),
+    };
+
+    for service in services {
+        let arn = service.task_definition.clone().ok_or(format_err!(
+            "Service {} has no task definition",
+            services::service_name(service)?
+        ))?;
+
+        let task_definition = if references {
+            TaskDefinitionSpec::Reference(
+                match task_definitions::task_definition_family_revision(&arn) {
+                    Some((family, revision)) => format!("{}:{}", family, revision),
+                    None => arn,
+                },
+            )
+        } else {
+            let mut req = task_definitions::register_request(
+                &task_definitions::describe_task_definition(ecs_client, arn)?,
+            )?;
+            task_definitions::strip_defaults(&mut req);
+            TaskDefinitionSpec::Definition(Box::new(TaskDefinitionBody::from(req)))
+        };
+
+        let mut spec = dumped_spec(service, task_definition);
+        if !desired_counts {
+            spec.desired_count = None;
+        }
+
+        manifest
+            .services
+            .insert(services::service_name(service)?, spec);
+    }
+
+    Ok(manifest)
+}
+
+/// A service's spec as `dump` writes it
+fn dumped_spec(service: &Service, task_definition: TaskDefinitionSpec) -> ServiceSpec {
+    let mut spec = service_spec(service, task_definition);
+    normalize(&mut spec);
+    strip_defaults(&mut spec);
+    spec
+}
+
+/// Clears service settings that have the values ECS picks when they are left out
+fn strip_defaults(spec: &mut ServiceSpec) {
+    let default_deployment = spec.deployment_configuration.as_ref().is_some_and(|d| {
+        d.maximum_percent.is_none_or(|p| p == 200)
+            && d.minimum_healthy_percent.is_none_or(|p| p == 100)
+    });
+    if default_deployment {
+        spec.deployment_configuration = None;
+    }
+    if spec.launch_type.as_deref() == Some("EC2") {
+        spec.launch_type = None;
+    }
+    if spec.platform_version.as_deref() == Some("LATEST") {
+        spec.platform_version = None;
+    }
+    if spec.health_check_grace_period_seconds == Some(0) {
+        spec.health_check_grace_period_seconds = None;
+    }
+
+    // The service-linked role is used when no role is given, and can't be given explicitly.
+    // Other roles are written as names unless they have a path
+    spec.role = match spec.role.take() {
+        Some(ref arn) if arn.contains("/aws-service-role/") => None,
+        Some(arn) => match arn.split(":role/").nth(1) {
+            Some(name) if !name.contains('/') => Some(name.to_string()),
+            _ => Some(arn),
+        },
+        None => None,
+    };
+}
+
+/// Writes a manifest in the given format, leaving out empty lists and objects
+pub fn render(manifest: &Manifest, format: InputFormat) -> Result<String, Error> {
+    let mut value = serde_json::to_value(manifest)?;
+    prune(&mut value);
+
+    Ok(match format {
+        InputFormat::Json => serde_json::to_string_pretty(&value)?,
+        InputFormat::Yaml => serde_yaml::to_string(&value)?,
+        // Going through a TOML value puts plain values ahead of tables, as TOML requires
+        InputFormat::Toml => toml::to_string_pretty(&toml::Value::try_from(&value)?)?,
+    })
+}
+
+/// Drops nulls and empty lists and objects from JSON objects
+fn prune(value: &mut Value) {
+    match *value {
+        Value::Object(ref mut map) => {
+            *map = mem::replace(map, Map::new())
+                .into_iter()
+                .filter_map(|(key, mut value)| {
+                    prune(&mut value);
+                    let empty = match value {
+                        Value::Null => true,
+                        Value::Array(ref items) => items.is_empty(),
+                        Value::Object(ref map) => map.is_empty(),
+                        _ => false,
+                    };
+                    if empty {
+                        None
+                    } else {
+                        Some((key, value))
+                    }
+                })
+                .collect();
+        }
+        Value::Array(ref mut items) => {
+            for item in items {
+                prune(item);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Makes a planned change to create or update a service
+pub fn apply(ecs_client: &EcsClient, cluster: &str, change: Change) -> Result<(), Error> {
+    let task_definition = match change.register {
+        Some(req) => Some(
+            task_definitions::register_task_definition(ecs_client, req)?
+                .task_definition_arn
+                .ok_or(format_err!("Registered task definition has no ARN"))?,
+        ),
+        None => None,
+    };
+
+    match change.action {
+        Action::Create(mut req) => {
+            if let Some(task_definition) = task_definition {
+                req.task_definition = task_definition;
+            }
+            services::send_create_service(ecs_client, req)?;
+        }
+        Action::Update(mut req) => {
+            if task_definition.is_some() {
+                req.task_definition = task_definition;
+            }
+            services::send_update_service(
+                ecs_client,
+                req,
+                format!("Updating {}/{}", cluster, change.service),
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rusoto_core::Region;
+    use rusoto_ecs::AwsVpcConfiguration;
+
+    const ARN: &str = "arn:aws:ecs:us-east-1:123456789012:task-definition/app:3";
+
+    fn service() -> Service {
+        Service {
+            service_name: Some(String::from("api")),
+            task_definition: Some(ARN.to_string()),
+            desired_count: Some(2),
+            deployment_configuration: Some(DeploymentConfiguration {
+                maximum_percent: Some(200),
+                minimum_healthy_percent: Some(100),
+            }),
+            network_configuration: Some(NetworkConfiguration {
+                awsvpc_configuration: Some(AwsVpcConfiguration {
+                    assign_public_ip: Some(String::from("DISABLED")),
+                    security_groups: Some(vec![String::from("sg-2"), String::from("sg-1")]),
+                    subnets: vec![String::from("subnet-b"), String::from("subnet-a")],
+                }),
+            }),
+            launch_type: Some(String::from("FARGATE")),
+            platform_version: Some(String::from("LATEST")),
+            health_check_grace_period_seconds: Some(30),
+            role_arn: Some(String::from(
+                "arn:aws:iam::123456789012:role/aws-service-role/ecs.amazonaws.com/AWSServiceRoleForECS",
+            )),
+            load_balancers: Some(vec![LoadBalancer {
+                container_name: Some(String::from("web")),
+                container_port: Some(8080),
+                load_balancer_name: None,
+                target_group_arn: Some(String::from(
+                    "arn:aws:elasticloadbalancing:us-east-1:123456789012:targetgroup/api/abc",
+                )),
+            }]),
+            ..Default::default()
+        }
+    }
+
+    fn parse_manifest(text: &str) -> Result<Manifest, Error> {
+        input::parse("manifest.json", text, None)
+    }
+
+    #[test]
+    fn refers_to_references() {
+        assert!(refers_to(ARN, ARN));
+        assert!(refers_to(ARN, "app"));
+        assert!(refers_to(ARN, "app:3"));
+        assert!(!refers_to(ARN, "app:4"));
+        assert!(!refers_to(ARN, "other"));
+        assert!(!refers_to(
+            ARN,
+            "arn:aws:ecs:us-east-1:123456789012:task-definition/app:4"
+        ));
+    }
+
+    #[test]
+    fn normalize_network_configuration() {
+        let mut spec = service_spec(&service(), TaskDefinitionSpec::Reference(ARN.to_string()));
+        normalize(&mut spec);
+
+        let awsvpc = spec
+            .network_configuration
+            .and_then(|n| n.awsvpc_configuration)
+            .unwrap();
+        assert_eq!(awsvpc.subnets, vec!["subnet-a", "subnet-b"]);
+        assert_eq!(
+            awsvpc.security_groups,
+            Some(vec![String::from("sg-1"), String::from("sg-2")])
+        );
+        assert_eq!(awsvpc.assign_public_ip, None);
+    }
+
+    #[test]
+    fn strip_service_defaults() {
+        let mut spec = service_spec(&service(), TaskDefinitionSpec::Reference(ARN.to_string()));
+        strip_defaults(&mut spec);
+
+        assert!(spec.deployment_configuration.is_none());
+        assert!(spec.platform_version.is_none());
+        assert!(spec.role.is_none());
+        assert_eq!(spec.launch_type, Some(String::from("FARGATE")));
+        assert_eq!(spec.health_check_grace_period_seconds, Some(30));
+
+        let mut spec = ServiceSpec {
+            launch_type: Some(String::from("EC2")),
+            health_check_grace_period_seconds: Some(0),
+            role: Some(String::from(
+                "arn:aws:iam::123456789012:role/ecsServiceRole",
+            )),
+            ..spec
+        };
+        strip_defaults(&mut spec);
+        assert!(spec.launch_type.is_none());
+        assert!(spec.health_check_grace_period_seconds.is_none());
+        assert_eq!(spec.role, Some(String::from("ecsServiceRole")));
+
+        let mut spec = ServiceSpec {
+            role: Some(String::from("arn:aws:iam::123456789012:role/team/ecs")),
+            ..spec
+        };
+        strip_defaults(&mut spec);
+        assert_eq!(
+            spec.role,
+            Some(String::from("arn:aws:iam::123456789012:role/team/ecs"))
+        );
+    }
+
+    #[test]
+    fn prune_empty_values() {
+        let mut value = json!({
+            "a": null,
+            "b": [],
+            "c": {"d": {}, "e": [{"f": null, "g": 1}]},
+            "h": {"i": null},
+            "j": 0,
+        });
+        prune(&mut value);
+
+        assert_eq!(value, json!({"c": {"e": [{"g": 1}]}, "j": 0}));
+    }
+
+    #[test]
+    fn task_definitions_by_shape() {
+        let manifest = parse_manifest(
+            r#"{"cluster": "c", "region": "us-east-1", "services": {
+                "api": {"taskDefinition": "app:3"},
+                "web": {"taskDefinition": {"family": "web", "containerDefinitions": []}}
+            }}"#,
+        )
+        .unwrap();
+
+        match manifest.services["api"].task_definition {
+            TaskDefinitionSpec::Reference(ref reference) => assert_eq!(reference, "app:3"),
+            ref other => panic!("Expected a reference, got {:?}", other),
+        }
+        assert_eq!(manifest.services["api"].desired_count, None);
+        match manifest.services["web"].task_definition {
+            TaskDefinitionSpec::Definition(ref body) => assert_eq!(body.family, "web"),
+            ref other => panic!("Expected a definition, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn task_definition_errors_locate_the_field() {
+        let error = parse_manifest(
+            r#"{"cluster": "c", "region": "us-east-1", "services": {
+                "api": {"taskDefinition": {"family": "app", "containerDefinition": []}}
+            }}"#,
+        )
+        .unwrap_err()
+        .to_string();
+
+        assert!(
+            error.contains("unknown field `containerDefinition`"),
+            "{}",
+            error
+        );
+        assert!(error.contains("line 2"), "{}", error);
+    }
+
+    #[test]
+    fn dumped_manifests_plan_no_changes() {
+        let services = vec![service()];
+        let mut manifest = Manifest {
+            cluster: String::from("c"),
+            region: String::from("us-east-1"),
+            services: BTreeMap::new(),
+        };
+        manifest.services.insert(
+            String::from("api"),
+            dumped_spec(
+                &services[0],
+                TaskDefinitionSpec::Reference(String::from("app:3")),
+            ),
+        );
+
+        let ecs_client = EcsClient::new(Region::UsEast1);
+        for format in &[InputFormat::Json, InputFormat::Yaml, InputFormat::Toml] {
+            let text = render(&manifest, *format).unwrap();
+            let parsed: Manifest = input::parse("manifest", &text, Some(*format)).unwrap();
+
+            let plan = plan(&ecs_client, &parsed, &services, true).unwrap();
+            assert!(plan.changes.is_empty(), "{}: {:?}", format, plan.changes);
+            assert!(plan.deletions.is_empty());
+            assert!(plan.fixed.is_empty(), "{}: {:?}", format, plan.fixed);
+            assert!(plan.unmanaged.is_empty());
+        }
+    }
+
+    #[test]
+    fn omitted_desired_counts_are_unmanaged() {
+        let service = service();
+        let mut spec = dumped_spec(&service, TaskDefinitionSpec::Reference(String::from("app")));
+        spec.desired_count = None;
+
+        let ecs_client = EcsClient::new(Region::UsEast1);
+        let (change, fixed) = plan_update(&ecs_client, "c", &spec, &service).unwrap();
+        assert!(change.is_none());
+        assert!(fixed.is_empty());
+
+        spec.desired_count = Some(5);
+        let (change, _) = plan_update(&ecs_client, "c", &spec, &service).unwrap();
+        match change.unwrap().action {
+            Action::Update(req) => assert_eq!(req.desired_count, Some(5)),
+            other => panic!("Expected an update, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn services_without_task_definitions() {
+        let spec = dumped_spec(
+            &service(),
+            TaskDefinitionSpec::Reference(String::from("app")),
+        );
+        let service = Service {
+            task_definition: None,
+            ..service()
+        };
+
+        let ecs_client = EcsClient::new(Region::UsEast1);
+        let error = plan_update(&ecs_client, "c", &spec, &service).unwrap_err();
+        assert_eq!(error.to_string(), "Service api has no task definition");
+    }
+}
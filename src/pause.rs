@@ -0,0 +1,66 @@
+use failure::Error;
+use serde_json;
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::path::{Path, PathBuf};
+
+/// The desired counts of a cluster's services before it was paused
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PauseState {
+    pub cluster: String,
+    pub region: String,
+    /// The UTC date the cluster was paused, `YYYY-MM-DD`
+    pub paused_at: String,
+    pub desired_counts: BTreeMap<String, i64>,
+}
+
+/// Where the pause state of a cluster is kept when no path is given
+pub fn default_path(cluster: &str, region: &str) -> PathBuf {
+    PathBuf::from(format!("ecs-manage-paused-{}-{}.json", region, cluster))
+}
+
+impl PauseState {
+    /// Writes the state, refusing to replace an existing one. Pausing twice would otherwise
+    /// record the zeroed counts and lose the ones to resume to
+    pub fn save(&self, path: &Path) -> Result<(), Error> {
+        let file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(path)
+            .map_err(|e| {
+                format_err!(
+                    "Can't record pause state in {}: {}. Is the cluster already paused?",
+                    path.display(),
+                    e
+                )
+            })?;
+
+        serde_json::to_writer_pretty(file, self)?;
+        Ok(())
+    }
+
+    pub fn load(path: &Path, cluster: &str, region: &str) -> Result<Self, Error> {
+        let state: PauseState =
+            serde_json::from_reader(File::open(path).map_err(|e| {
+                format_err!("Can't read pause state from {}: {}", path.display(), e)
+            })?)?;
+
+        if state.cluster != cluster || state.region != region {
+            bail!(
+                "{} records {}/{}, not {}/{}",
+                path.display(),
+                state.region,
+                state.cluster,
+                region,
+                cluster
+            );
+        }
+
+        Ok(state)
+    }
+
+    /// Removes the state once every service is restored, so the cluster can be paused again
+    pub fn remove(path: &Path) -> Result<(), Error> {
+        Ok(fs::remove_file(path)?)
+    }
+}
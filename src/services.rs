@@ -1,17 +1,26 @@
 use backoff;
 use failure::Error;
-use rusoto_ecr::{DescribeImagesRequest, Ecr, EcrClient, ImageDetail, ImageIdentifier};
+use rusoto_ecr::{
+    DescribeImagesError, DescribeImagesRequest, Ecr, EcrClient, ImageDetail, ImageIdentifier,
+};
 use rusoto_ecs::{
-    CreateServiceRequest, DescribeServicesError, DescribeServicesRequest,
-    DescribeTaskDefinitionError, DescribeTaskDefinitionRequest, Ecs, EcsClient, ListServicesError,
-    ListServicesRequest, Service, UpdateServiceError, UpdateServiceRequest,
+    CreateServiceError, CreateServiceRequest, DeleteServiceError, DeleteServiceRequest,
+    DescribeServicesError, DescribeServicesRequest, Ecs, EcsClient, ListServicesError,
+    ListServicesRequest, RegisterTaskDefinitionRequest, Service, UpdateServiceError,
+    UpdateServiceRequest,
 };
 use rusoto_elbv2::{
-    DescribeTargetGroupsError, DescribeTargetGroupsInput, Elb, ElbClient, TargetGroup,
+    DescribeTargetGroupsError, DescribeTargetGroupsInput, DescribeTargetHealthError,
+    DescribeTargetHealthInput, Elb, ElbClient, TargetGroup, TargetHealthDescription,
 };
 
+use std::thread;
+use std::time::{Duration, Instant};
+
 use args::*;
 use helpers;
+use images::{self, EcrRegistry, ImageReference};
+use task_definitions;
 
 pub fn service_name(service: &Service) -> Result<String, Error> {
     match service.service_name {
@@ -26,8 +35,8 @@ pub fn compare_services(
     destination_ecs_client: &EcsClient,
     destination_cluster: String,
 ) -> Result<Vec<Service>, Error> {
-    let source_services = describe_services(&source_ecs_client, source_cluster)?;
-    let destination_services = describe_services(&destination_ecs_client, destination_cluster)?;
+    let source_services = describe_services(source_ecs_client, source_cluster)?;
+    let destination_services = describe_services(destination_ecs_client, destination_cluster)?;
 
     let destination_names = destination_services
         .into_iter()
@@ -113,9 +122,9 @@ pub fn describe_service(
 }
 
 pub fn describe_services(ecs_client: &EcsClient, cluster: String) -> Result<Vec<Service>, Error> {
-    list_services(&ecs_client, cluster.clone())?
+    list_services(ecs_client, cluster.clone())?
         .into_iter()
-        .map(|service| describe_service(&ecs_client, cluster.clone(), service))
+        .map(|service| describe_service(ecs_client, cluster.clone(), service))
         .collect()
 }
 
@@ -127,12 +136,12 @@ pub fn create_service(
 ) -> Result<Option<Service>, Error> {
     let has_loadbalancer = from_service
         .load_balancers
-        .clone()
-        .map_or(false, |l| !l.is_empty());
+        .as_ref()
+        .is_some_and(|l| !l.is_empty());
     let is_awsvpc = from_service
         .network_configuration
-        .clone()
-        .map_or(false, |n| n.awsvpc_configuration.is_some());
+        .as_ref()
+        .is_some_and(|n| n.awsvpc_configuration.is_some());
 
     let role = if has_loadbalancer && !is_awsvpc {
         Some(format!(
@@ -203,24 +212,34 @@ pub fn update_service(
 ) -> Result<Service, Error> {
     let service_name = service_name(&service)?;
 
-    let template_req = UpdateServiceRequest {
-        cluster: Some(cluster.clone()),
-        deployment_configuration: None,
-        desired_count: None,
-        force_new_deployment: None,
-        health_check_grace_period_seconds: None,
-        network_configuration: None,
-        platform_version: None,
-        service: service_name.clone(),
-        task_definition: None,
-    };
+    let template_req = update_service_template(cluster.clone(), service_name.clone());
 
     let (req, summary) = match modification {
-        ServiceModification::DesiredCount { count } => {
+        ServiceModification::DesiredCount { count, min, max } => {
             let count = match count {
                 DesiredCountOptions::Number(count) => Some(count),
-                DesiredCountOptions::Map(map) => map.get(&service_name).map(|c| c.clone()),
-            };
+                DesiredCountOptions::Relative(adjustment) => Some(
+                    adjustment.apply(
+                        service
+                            .desired_count
+                            .ok_or(format_err!("Service {} has no desired count", service_name))?,
+                    ),
+                ),
+                DesiredCountOptions::Map(map) => match map.get(&service_name) {
+                    Some(&count) => Some(count),
+                    None => {
+                        println!(
+                            "Skipping {}/{}, which is not in the map",
+                            cluster, service_name
+                        );
+                        return Ok(service);
+                    }
+                },
+            }
+            .map(|count| {
+                let count = min.map_or(count, |min| count.max(min));
+                max.map_or(count, |max| count.min(max))
+            });
 
             let summary = format!("desired count to {:?}", count);
 
@@ -237,20 +256,261 @@ pub fn update_service(
                 summary,
             )
         }
+        ServiceModification::Image {
+            image,
+            container,
+            map,
+        } => {
+            let image = match (image, map) {
+                (Some(_), Some(_)) => {
+                    bail!("The image can come from either --map or the argument, not both")
+                }
+                (None, None) => bail!("Either an image or --map is needed"),
+                (Some(image), None) => image,
+                (None, Some(map)) => match map.get(&service_name) {
+                    Some(image) => image.clone(),
+                    None => {
+                        println!(
+                            "Skipping {}/{}, which is not in the map",
+                            cluster, service_name
+                        );
+                        return Ok(service);
+                    }
+                },
+            };
+
+            let revision = register_revision(ecs_client, &service, |req| {
+                let container_definition =
+                    task_definitions::container_mut(req, container.as_deref())?;
+                let current = container_definition.image.clone().unwrap_or_default();
+                let image = images::replace_image(&current, &image)?;
+
+                if image == current {
+                    println!(
+                        "Skipping {}/{}, which already runs {}",
+                        cluster, service_name, image
+                    );
+                    return Ok(None);
+                }
+
+                let summary = format!(
+                    "{} image to {}",
+                    container_definition.name.clone().unwrap_or_default(),
+                    image
+                );
+                println!(
+                    "Updating {}/{}'s {}. It was {}",
+                    cluster, service_name, summary, current
+                );
+
+                container_definition.image = Some(image);
+                Ok(Some(summary))
+            })?;
+
+            match revision {
+                Some((task_definition, summary)) => (
+                    UpdateServiceRequest {
+                        task_definition: Some(task_definition),
+                        ..template_req
+                    },
+                    summary,
+                ),
+                None => return Ok(service),
+            }
+        }
+        ServiceModification::Environment {
+            container,
+            set,
+            remove,
+            rename,
+        } => {
+            let revision = register_revision(ecs_client, &service, |req| {
+                let container_definition =
+                    task_definitions::container_mut(req, container.as_deref())?;
+                let container_name = container_definition.name.clone().unwrap_or_default();
+                let environment = container_definition
+                    .environment
+                    .get_or_insert_with(Vec::new);
+
+                let changes =
+                    task_definitions::edit_environment(environment, &set, &remove, &rename)?;
+                if changes.is_empty() {
+                    println!(
+                        "Skipping {}/{}, whose {} environment already matches",
+                        cluster, service_name, container_name
+                    );
+                    return Ok(None);
+                }
+
+                let summary = format!("{} environment: {}", container_name, changes.join(", "));
+                println!("Updating {}/{}'s {}", cluster, service_name, summary);
+
+                Ok(Some(summary))
+            })?;
+
+            match revision {
+                Some((task_definition, summary)) => (
+                    UpdateServiceRequest {
+                        task_definition: Some(task_definition),
+                        ..template_req
+                    },
+                    summary,
+                ),
+                None => return Ok(service),
+            }
+        }
+        ServiceModification::Resources {
+            container,
+            changes,
+            map,
+        } => {
+            let changes = match map {
+                Some(_) if !changes.is_empty() => {
+                    bail!("Resource changes can come from either --map or the flags, not both")
+                }
+                Some(map) => match map.get(&service_name) {
+                    Some(changes) => changes.clone(),
+                    None => {
+                        println!(
+                            "Skipping {}/{}, which is not in the map",
+                            cluster, service_name
+                        );
+                        return Ok(service);
+                    }
+                },
+                None => changes,
+            };
+
+            let revision = register_revision(ecs_client, &service, |req| {
+                let changes = task_definitions::resize(req, container.as_deref(), &changes)?;
+                if changes.is_empty() {
+                    println!(
+                        "Skipping {}/{}, whose resources already match",
+                        cluster, service_name
+                    );
+                    return Ok(None);
+                }
+
+                let summary = format!("resources: {}", changes.join(", "));
+                println!("Updating {}/{}'s {}", cluster, service_name, summary);
+
+                Ok(Some(summary))
+            })?;
+
+            match revision {
+                Some((task_definition, summary)) => (
+                    UpdateServiceRequest {
+                        task_definition: Some(task_definition),
+                        ..template_req
+                    },
+                    summary,
+                ),
+                None => return Ok(service),
+            }
+        }
     };
 
-    helpers::retry_log(
+    send_update_service(
+        ecs_client,
+        req,
         format!("Updating {}/{}'s {}", cluster, service_name, summary),
+    )
+}
+
+/// Registers a revision of the service's task definition with the changes `modify` makes.
+/// `modify` returns a summary of its changes, or `None` when there is nothing to change,
+/// in which case nothing is registered. Returns the new revision's ARN and the summary
+fn register_revision<F>(
+    ecs_client: &EcsClient,
+    service: &Service,
+    modify: F,
+) -> Result<Option<(String, String)>, Error>
+where
+    F: FnOnce(&mut RegisterTaskDefinitionRequest) -> Result<Option<String>, Error>,
+{
+    let task_definition = task_definitions::describe_task_definition(
+        ecs_client,
+        service.task_definition.clone().ok_or(format_err!(
+            "Service {} has no task definition",
+            service_name(service)?
+        ))?,
+    )?;
+
+    let mut req = task_definitions::register_request(&task_definition)?;
+    let summary = match modify(&mut req)? {
+        Some(summary) => summary,
+        None => return Ok(None),
+    };
+
+    let registered = task_definitions::register_task_definition(ecs_client, req)?;
+
+    Ok(Some((
+        registered
+            .task_definition_arn
+            .ok_or(format_err!("Registered task definition has no ARN"))?,
+        summary,
+    )))
+}
+
+/// An `UpdateServiceRequest` that changes nothing
+pub fn update_service_template(cluster: String, service_name: String) -> UpdateServiceRequest {
+    UpdateServiceRequest {
+        cluster: Some(cluster),
+        deployment_configuration: None,
+        desired_count: None,
+        force_new_deployment: None,
+        health_check_grace_period_seconds: None,
+        network_configuration: None,
+        platform_version: None,
+        service: service_name,
+        task_definition: None,
+    }
+}
+
+pub fn send_update_service(
+    ecs_client: &EcsClient,
+    req: UpdateServiceRequest,
+    summary: String,
+) -> Result<Service, Error> {
+    helpers::retry_log(summary, || {
+        ecs_client
+            .update_service(req.clone())
+            .sync()
+            .map_err(|e| match e {
+                UpdateServiceError::Unknown(s) => {
+                    if s == r#"{"__type":"ThrottlingException","message":"Rate exceeded"}"# {
+                        backoff::Error::Transient(UpdateServiceError::Unknown(s))
+                    } else {
+                        backoff::Error::Permanent(UpdateServiceError::Unknown(s))
+                    }
+                }
+                _ => backoff::Error::Permanent(e),
+            })
+    })?
+    .service
+    .ok_or(format_err!("Tried to update service, but nothing returned"))
+}
+
+pub fn send_create_service(
+    ecs_client: &EcsClient,
+    req: CreateServiceRequest,
+) -> Result<Service, Error> {
+    helpers::retry_log(
+        format!(
+            "Creating {}/{}",
+            req.cluster.clone().unwrap_or_default(),
+            req.service_name
+        ),
         || {
             ecs_client
-                .update_service(req.clone())
+                .create_service(req.clone())
                 .sync()
                 .map_err(|e| match e {
-                    UpdateServiceError::Unknown(s) => {
+                    CreateServiceError::Unknown(s) => {
                         if s == r#"{"__type":"ThrottlingException","message":"Rate exceeded"}"# {
-                            backoff::Error::Transient(UpdateServiceError::Unknown(s))
+                            backoff::Error::Transient(CreateServiceError::Unknown(s))
                         } else {
-                            backoff::Error::Permanent(UpdateServiceError::Unknown(s))
+                            backoff::Error::Permanent(CreateServiceError::Unknown(s))
                         }
                     }
                     _ => backoff::Error::Permanent(e),
@@ -258,182 +518,291 @@ pub fn update_service(
         },
     )?
     .service
-    .ok_or(format_err!("Tried to update service, but nothing returned"))
+    .ok_or(format_err!("Tried to create service, but nothing returned"))
 }
 
-pub fn audit_service(
+/// Deletes services one at a time, pausing `sleep` between them. Each is scaled to zero, which
+/// ECS requires before deleting it, and deleted once its tasks have stopped. A service whose
+/// tasks are still running after `drain_timeout` is left scaled to zero and the rest are still
+/// deleted. Any other error stops there. Either way, what happened to each service is printed
+pub fn delete_services(
     ecs_client: &EcsClient,
-    ecr_client: &EcrClient,
-    elb_client: &ElbClient,
-    service: &Service,
-) -> Result<Vec<String>, Error> {
-    let audit = hashmap![
-        "Invalid ECR images" => service_ecr_images(&ecs_client, &ecr_client, &service)?.iter().any(|r| r.is_err()),
-        "Invalid Target groups" => service_target_groups(&elb_client, &service)?.iter().any(|r| r.is_err()),
-        "Less than desired" => service.running_count.unwrap_or(0) < service.desired_count.unwrap_or(0)
-    ];
-
-    Ok(audit
-        .into_iter()
-        .filter(|(_, v)| *v)
-        .map(|(k, _)| String::from(k))
-        .collect::<Vec<String>>())
+    cluster: &str,
+    service_names: &[String],
+    drain_timeout: Duration,
+    sleep: Duration,
+) -> Result<(), Error> {
+    let mut deleted = Vec::new();
+    let mut scaled_down = Vec::new();
+    let mut failure = None;
+
+    for service_name in service_names {
+        match delete_drained_service(ecs_client, cluster, service_name, drain_timeout) {
+            Ok(true) => deleted.push(service_name.as_str()),
+            Ok(false) => scaled_down.push(service_name.as_str()),
+            Err(e) => {
+                failure = Some(e);
+                break;
+            }
+        }
+
+        thread::sleep(sleep);
+    }
+
+    if !deleted.is_empty() {
+        println!("Deleted: {}", deleted.join(", "));
+    }
+    if !scaled_down.is_empty() {
+        println!("Scaled to zero, not deleted: {}", scaled_down.join(", "));
+    }
+
+    if let Some(e) = failure {
+        return Err(e);
+    }
+    if !scaled_down.is_empty() {
+        bail!(
+            "{} services still had tasks after {} minutes and were not deleted",
+            scaled_down.len(),
+            drain_timeout.as_secs() / 60
+        );
+    }
+
+    Ok(())
 }
 
-pub fn service_ecr_images(
+/// Scales a service to zero and deletes it once its tasks have stopped. Returns whether it
+/// was deleted, which it isn't when its tasks don't stop in time
+fn delete_drained_service(
     ecs_client: &EcsClient,
-    ecr_client: &EcrClient,
-    service: &Service,
-) -> Result<Vec<Result<ImageDetail, Error>>, Error> {
-    match service.task_definition {
-        Some(ref task_definition) => {
-            let task_definition =
-                helpers::retry_log(format!("describing {}", task_definition), || {
-                    ecs_client
-                        .describe_task_definition(DescribeTaskDefinitionRequest {
-                            task_definition: task_definition.clone(),
-                        })
-                        .sync()
-                        .map_err(|e| match e {
-                            DescribeTaskDefinitionError::Unknown(s) => if s
-                                == r#"{"__type":"ThrottlingException","message":"Rate exceeded"}"# {
-                                backoff::Error::Transient(DescribeTaskDefinitionError::Unknown(s))
-                            } else {
-                                backoff::Error::Permanent(DescribeTaskDefinitionError::Unknown(s))
-                            },
-                            _ => backoff::Error::Permanent(e),
-                        })
-                })?
-                .task_definition;
-
-            match task_definition {
-                Some(task_definition) => match task_definition.container_definitions {
-                    Some(cds) => {
-                        let image_arns = cds
-                            .into_iter()
-                            .map(|cd| cd.image)
-                            .collect::<Vec<Option<String>>>();
-
-                        let mut images = Vec::new();
-
-                        for image_arn in image_arns {
-                            match image_arn {
-                                Some(image_arn) => {
-                                    match image_arn.split('/').collect::<Vec<&str>>().pop() {
-                                        Some(repo_image) => {
-                                            let split_repo_image =
-                                                repo_image.splitn(2, ':').collect::<Vec<&str>>();
-
-                                            let image_id = ImageIdentifier {
-                                                image_digest: None,
-                                                image_tag: Some(if split_repo_image.len() == 2 {
-                                                    split_repo_image[1].to_string()
-                                                } else {
-                                                    String::from("latest")
-                                                }),
-                                            };
-
-                                            let image_details_res = ecr_client
-                                                .describe_images(DescribeImagesRequest {
-                                                    filter: None,
-                                                    image_ids: Some(vec![image_id]),
-                                                    max_results: None,
-                                                    next_token: None,
-                                                    registry_id: None,
-                                                    repository_name: split_repo_image[0]
-                                                        .to_string(),
-                                                })
-                                                .sync();
-
-                                            match image_details_res {
-                                                Ok(image_details_res) => {
-                                                    match image_details_res.image_details {
-                                                        Some(mut image_details) => {
-                                                            match image_details.pop() {
-                                                                Some(image_detail) => {
-                                                                    images.push(Ok(image_detail));
-                                                                }
-                                                                None => {}
-                                                            }
-                                                        }
-                                                        None => {}
-                                                    }
-                                                }
-                                                Err(e) => images.push(Err(e.into())),
-                                            }
-                                        }
-                                        None => {}
-                                    }
-                                }
-                                None => {}
-                            }
-                        }
+    cluster: &str,
+    service_name: &str,
+    drain_timeout: Duration,
+) -> Result<bool, Error> {
+    send_update_service(
+        ecs_client,
+        UpdateServiceRequest {
+            desired_count: Some(0),
+            ..update_service_template(cluster.to_string(), service_name.to_string())
+        },
+        format!("Scaling {}/{} to 0", cluster, service_name),
+    )?;
+
+    if let Err(e) = wait_for_drain(
+        ecs_client,
+        cluster.to_string(),
+        service_name.to_string(),
+        drain_timeout,
+    ) {
+        warn!("{}, leaving it scaled to zero", e);
+        return Ok(false);
+    }
+
+    send_delete_service(ecs_client, cluster.to_string(), service_name.to_string())?;
 
-                        Ok(images)
+    Ok(true)
+}
+
+fn send_delete_service(
+    ecs_client: &EcsClient,
+    cluster: String,
+    service_name: String,
+) -> Result<Service, Error> {
+    helpers::retry_log(format!("Deleting {}/{}", cluster, service_name), || {
+        ecs_client
+            .delete_service(DeleteServiceRequest {
+                cluster: Some(cluster.clone()),
+                service: service_name.clone(),
+            })
+            .sync()
+            .map_err(|e| match e {
+                DeleteServiceError::Unknown(s) => {
+                    if s == r#"{"__type":"ThrottlingException","message":"Rate exceeded"}"# {
+                        backoff::Error::Transient(DeleteServiceError::Unknown(s))
+                    } else {
+                        backoff::Error::Permanent(DeleteServiceError::Unknown(s))
                     }
-                    None => Ok(Vec::new()),
-                },
-                None => Ok(Vec::new()),
-            }
+                }
+                _ => backoff::Error::Permanent(e),
+            })
+    })?
+    .service
+    .ok_or(format_err!("Tried to delete service, but nothing returned"))
+}
+
+/// Waits until none of a service's tasks are running or pending
+pub fn wait_for_drain(
+    ecs_client: &EcsClient,
+    cluster: String,
+    service_name: String,
+    timeout: Duration,
+) -> Result<(), Error> {
+    let started = Instant::now();
+
+    loop {
+        let service = describe_service(ecs_client, cluster.clone(), service_name.clone())?;
+        let remaining =
+            service.running_count.unwrap_or_default() + service.pending_count.unwrap_or_default();
+
+        if remaining == 0 {
+            return Ok(());
         }
-        None => Ok(Vec::new()),
+        if started.elapsed() >= timeout {
+            bail!(
+                "{}/{} still has {} tasks after {} minutes",
+                cluster,
+                service_name,
+                remaining,
+                timeout.as_secs() / 60
+            );
+        }
+
+        info!(
+            "Waiting for {} tasks of {}/{} to stop",
+            remaining, cluster, service_name
+        );
+        thread::sleep(Duration::from_secs(10));
+    }
+}
+
+/// The ECR lookup made for a single container's image
+#[derive(Debug)]
+pub struct ContainerImage {
+    pub container_name: String,
+    pub image: ImageReference,
+    pub image_detail: Result<ImageDetail, DescribeImagesError>,
+}
+
+impl ContainerImage {
+    /// Names the ECR error the way AWS reports it, e.g. `ImageNotFoundException`
+    pub fn error_summary(&self) -> Option<String> {
+        self.image_detail.as_ref().err().map(|e| match *e {
+            DescribeImagesError::ImageNotFound(ref message) => {
+                format!("ImageNotFoundException: {}", message)
+            }
+            DescribeImagesError::RepositoryNotFound(ref message) => {
+                format!("RepositoryNotFoundException: {}", message)
+            }
+            DescribeImagesError::InvalidParameter(ref message) => {
+                format!("InvalidParameterException: {}", message)
+            }
+            DescribeImagesError::Server(ref message) => format!("ServerException: {}", message),
+            ref e => format!("{:?}", e),
+        })
     }
+
+    /// Whether ECR reported that the image or its repository doesn't exist, as opposed to
+    /// failing to look it up
+    pub fn is_missing(&self) -> bool {
+        matches!(
+            self.image_detail,
+            Err(DescribeImagesError::ImageNotFound(_))
+                | Err(DescribeImagesError::RepositoryNotFound(_))
+        )
+    }
+}
+
+/// Looks up an image in the ECR registry it was pulled from, preferring its digest over its tag
+pub fn describe_ecr_image(
+    ecr_client: &EcrClient,
+    image: &ImageReference,
+    registry: &EcrRegistry,
+) -> Result<ImageDetail, DescribeImagesError> {
+    let image_id = match image.digest {
+        Some(ref digest) => ImageIdentifier {
+            image_digest: Some(digest.clone()),
+            image_tag: None,
+        },
+        None => ImageIdentifier {
+            image_digest: None,
+            image_tag: Some(image.tag_or_latest()),
+        },
+    };
+
+    helpers::retry_log(format!("describing image {}", image), || {
+        ecr_client
+            .describe_images(DescribeImagesRequest {
+                filter: None,
+                image_ids: Some(vec![image_id.clone()]),
+                max_results: None,
+                next_token: None,
+                registry_id: Some(registry.account_id.clone()),
+                repository_name: image.repository.clone(),
+            })
+            .sync()
+            .map_err(|e| match e {
+                DescribeImagesError::Unknown(s) => {
+                    if s == r#"{"__type":"ThrottlingException","message":"Rate exceeded"}"# {
+                        backoff::Error::Transient(DescribeImagesError::Unknown(s))
+                    } else {
+                        backoff::Error::Permanent(DescribeImagesError::Unknown(s))
+                    }
+                }
+                _ => backoff::Error::Permanent(e),
+            })
+    })
+    .map_err(|e| match e {
+        backoff::Error::Transient(e) | backoff::Error::Permanent(e) => e,
+    })
+    .and_then(|res| {
+        res.image_details
+            .and_then(|mut image_details| image_details.pop())
+            .ok_or_else(|| DescribeImagesError::Unknown(String::from("No image details returned")))
+    })
 }
 
+/// A target group's ARN and the result of looking it up
+pub type TargetGroupLookup = (String, Result<TargetGroup, Error>);
+
+/// The target groups attached to a service
 pub fn service_target_groups(
     elb_client: &ElbClient,
     service: &Service,
-) -> Result<Vec<Result<TargetGroup, Error>>, Error> {
+) -> Result<Vec<TargetGroupLookup>, Error> {
     match service.load_balancers {
         Some(ref load_balancers) => {
             let mut target_groups = Vec::new();
 
-            for target_group_arn in load_balancers.iter().map(|lb| lb.target_group_arn.clone()) {
-                match target_group_arn {
-                    Some(target_group_arn) => {
-                        let target_groups_res =
-                            helpers::retry_log(format!("describing {}", target_group_arn), || {
-                                elb_client
-                                    .describe_target_groups(DescribeTargetGroupsInput {
-                                        load_balancer_arn: None,
-                                        marker: None,
-                                        names: None,
-                                        page_size: None,
-                                        target_group_arns: Some(vec![target_group_arn.clone()]),
-                                    })
-                                    .sync()
-                                    .map_err(|e| match e {
-                                        DescribeTargetGroupsError::Unknown(s) => {
-                                            if s.contains("<Code>Throttling</Code>") {
-                                                backoff::Error::Transient(
-                                                    DescribeTargetGroupsError::Unknown(s),
-                                                )
-                                            } else {
-                                                backoff::Error::Permanent(
-                                                    DescribeTargetGroupsError::Unknown(s),
-                                                )
-                                            }
-                                        }
-                                        _ => backoff::Error::Permanent(e),
-                                    })
-                            });
-
-                        match target_groups_res {
-                            Ok(target_groups_res) => match target_groups_res.target_groups {
-                                Some(mut target_group_details) => {
-                                    match target_group_details.pop() {
-                                        Some(target_group_detail) => {
-                                            target_groups.push(Ok(target_group_detail));
-                                        }
-                                        None => {}
+            for target_group_arn in load_balancers
+                .iter()
+                .filter_map(|lb| lb.target_group_arn.clone())
+            {
+                let target_groups_res =
+                    helpers::retry_log(format!("describing {}", target_group_arn), || {
+                        elb_client
+                            .describe_target_groups(DescribeTargetGroupsInput {
+                                load_balancer_arn: None,
+                                marker: None,
+                                names: None,
+                                page_size: None,
+                                target_group_arns: Some(vec![target_group_arn.clone()]),
+                            })
+                            .sync()
+                            .map_err(|e| match e {
+                                DescribeTargetGroupsError::Unknown(s) => {
+                                    if s.contains("<Code>Throttling</Code>") {
+                                        backoff::Error::Transient(
+                                            DescribeTargetGroupsError::Unknown(s),
+                                        )
+                                    } else {
+                                        backoff::Error::Permanent(
+                                            DescribeTargetGroupsError::Unknown(s),
+                                        )
                                     }
                                 }
-                                None => {}
-                            },
-                            Err(e) => target_groups.push(Err(e.into())),
+                                _ => backoff::Error::Permanent(e),
+                            })
+                    });
+
+                match target_groups_res {
+                    Ok(target_groups_res) => {
+                        if let Some(target_group_detail) = target_groups_res
+                            .target_groups
+                            .and_then(|mut target_group_details| target_group_details.pop())
+                        {
+                            target_groups.push((target_group_arn, Ok(target_group_detail)));
                         }
                     }
-                    None => {}
+                    Err(e) => target_groups.push((target_group_arn, Err(e.into()))),
                 }
             }
 
@@ -442,3 +811,43 @@ pub fn service_target_groups(
         None => Ok(Vec::new()),
     }
 }
+
+/// Whether an error from `service_target_groups` says the target group doesn't exist, as
+/// opposed to failing to look it up
+pub fn is_target_group_not_found(e: &Error) -> bool {
+    matches!(
+        e.downcast_ref::<backoff::Error<DescribeTargetGroupsError>>(),
+        Some(backoff::Error::Permanent(
+            DescribeTargetGroupsError::TargetGroupNotFound(_)
+        ))
+    )
+}
+
+pub fn describe_target_health(
+    elb_client: &ElbClient,
+    target_group_arn: String,
+) -> Result<Vec<TargetHealthDescription>, Error> {
+    Ok(helpers::retry_log(
+        format!("describing target health of {}", target_group_arn),
+        || {
+            elb_client
+                .describe_target_health(DescribeTargetHealthInput {
+                    target_group_arn: target_group_arn.clone(),
+                    targets: None,
+                })
+                .sync()
+                .map_err(|e| match e {
+                    DescribeTargetHealthError::Unknown(s) => {
+                        if s.contains("<Code>Throttling</Code>") {
+                            backoff::Error::Transient(DescribeTargetHealthError::Unknown(s))
+                        } else {
+                            backoff::Error::Permanent(DescribeTargetHealthError::Unknown(s))
+                        }
+                    }
+                    _ => backoff::Error::Permanent(e),
+                })
+        },
+    )?
+    .target_health_descriptions
+    .unwrap_or_default())
+}
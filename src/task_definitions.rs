@@ -0,0 +1,1006 @@
+use backoff;
+use failure::Error;
+use rusoto_ecs::{
+    ContainerDefinition, DeregisterTaskDefinitionError, DeregisterTaskDefinitionRequest,
+    DescribeTaskDefinitionError, DescribeTaskDefinitionRequest, Ecs, EcsClient, KeyValuePair,
+    ListTaskDefinitionsError, ListTaskDefinitionsRequest, RegisterTaskDefinitionError,
+    RegisterTaskDefinitionRequest, Service, TaskDefinition,
+};
+use serde_json::{self, Value};
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+
+use args::{Assignment, ResourceChanges};
+use helpers;
+use images;
+
+/// The newest active revision of a task definition's family that is older than it
+pub fn previous_task_definition(
+    ecs_client: &EcsClient,
+    task_definition: &TaskDefinition,
+) -> Result<Option<String>, Error> {
+    let family = task_definition
+        .family
+        .clone()
+        .ok_or(format_err!("Task definition has no family"))?;
+    let revision = task_definition
+        .revision
+        .ok_or(format_err!("Task definition {} has no revision", family))?;
+
+    Ok(
+        list_task_definitions(ecs_client, Some(family.clone()), "ACTIVE")?
+            .into_iter()
+            .filter_map(|arn| {
+                let (arn_family, arn_revision) = task_definition_family_revision(&arn)?;
+                if arn_family == family && arn_revision < revision {
+                    Some((arn_revision, arn))
+                } else {
+                    None
+                }
+            })
+            .max()
+            .map(|(_, arn)| arn),
+    )
+}
+
+/// Splits `arn:aws:ecs:<region>:<account>:task-definition/<family>:<revision>`
+/// into its family and revision
+pub fn task_definition_family_revision(arn: &str) -> Option<(String, i64)> {
+    let family_revision = arn.rsplit('/').next()?;
+    let mut parts = family_revision.rsplitn(2, ':');
+    let revision = parts.next()?.parse::<i64>().ok()?;
+    let family = parts.next()?;
+
+    Some((family.to_string(), revision))
+}
+
+pub fn list_task_definitions(
+    ecs_client: &EcsClient,
+    family_prefix: Option<String>,
+    status: &str,
+) -> Result<Vec<String>, Error> {
+    let mut token = Some(String::new());
+
+    let mut task_definitions = Vec::new();
+
+    while token.is_some() {
+        let res = helpers::retry_log(
+            format!(
+                "listing task definitions of {}",
+                family_prefix
+                    .clone()
+                    .unwrap_or_else(|| String::from("every family"))
+            ),
+            || {
+                ecs_client
+                    .list_task_definitions(ListTaskDefinitionsRequest {
+                        family_prefix: family_prefix.clone(),
+                        max_results: None,
+                        next_token: token.clone(),
+                        sort: None,
+                        status: Some(status.to_string()),
+                    })
+                    .sync()
+                    .map_err(|e| match e {
+                        ListTaskDefinitionsError::Unknown(s) => {
+                            if s == r#"{"__type":"ThrottlingException","message":"Rate exceeded"}"# {
+                                backoff::Error::Transient(ListTaskDefinitionsError::Unknown(s))
+                            } else {
+                                backoff::Error::Permanent(ListTaskDefinitionsError::Unknown(s))
+                            }
+                        }
+                        _ => backoff::Error::Permanent(e),
+                    })
+            },
+        )?;
+        if let Some(mut arns) = res.task_definition_arns {
+            task_definitions.append(&mut arns)
+        };
+
+        token = res.next_token;
+    }
+
+    Ok(task_definitions)
+}
+
+pub fn describe_task_definition(
+    ecs_client: &EcsClient,
+    task_definition: String,
+) -> Result<TaskDefinition, Error> {
+    helpers::retry_log(format!("describing {}", task_definition), || {
+        ecs_client
+            .describe_task_definition(DescribeTaskDefinitionRequest {
+                task_definition: task_definition.clone(),
+            })
+            .sync()
+            .map_err(|e| match e {
+                DescribeTaskDefinitionError::Unknown(s) => {
+                    if s == r#"{"__type":"ThrottlingException","message":"Rate exceeded"}"# {
+                        backoff::Error::Transient(DescribeTaskDefinitionError::Unknown(s))
+                    } else {
+                        backoff::Error::Permanent(DescribeTaskDefinitionError::Unknown(s))
+                    }
+                }
+                _ => backoff::Error::Permanent(e),
+            })
+    })?
+    .task_definition
+    .ok_or(format_err!(
+        "No task definition found for {}",
+        task_definition
+    ))
+}
+
+/// A request that registers a copy of a task definition as a new revision of its family
+pub fn register_request(
+    task_definition: &TaskDefinition,
+) -> Result<RegisterTaskDefinitionRequest, Error> {
+    Ok(RegisterTaskDefinitionRequest {
+        container_definitions: task_definition
+            .container_definitions
+            .clone()
+            .unwrap_or_default(),
+        cpu: task_definition.cpu.clone(),
+        execution_role_arn: task_definition.execution_role_arn.clone(),
+        family: task_definition
+            .family
+            .clone()
+            .ok_or(format_err!("Task definition has no family"))?,
+        memory: task_definition.memory.clone(),
+        network_mode: task_definition.network_mode.clone(),
+        placement_constraints: task_definition.placement_constraints.clone(),
+        requires_compatibilities: task_definition.requires_compatibilities.clone(),
+        task_role_arn: task_definition.task_role_arn.clone(),
+        volumes: task_definition.volumes.clone(),
+    })
+}
+
+pub fn register_task_definition(
+    ecs_client: &EcsClient,
+    req: RegisterTaskDefinitionRequest,
+) -> Result<TaskDefinition, Error> {
+    helpers::retry_log(format!("Registering a revision of {}", req.family), || {
+        ecs_client
+            .register_task_definition(req.clone())
+            .sync()
+            .map_err(|e| match e {
+                RegisterTaskDefinitionError::Unknown(s) => {
+                    if s == r#"{"__type":"ThrottlingException","message":"Rate exceeded"}"# {
+                        backoff::Error::Transient(RegisterTaskDefinitionError::Unknown(s))
+                    } else {
+                        backoff::Error::Permanent(RegisterTaskDefinitionError::Unknown(s))
+                    }
+                }
+                _ => backoff::Error::Permanent(e),
+            })
+    })?
+    .task_definition
+    .ok_or(format_err!(
+        "Tried to register task definition, but nothing returned"
+    ))
+}
+
+pub fn deregister_task_definition(
+    ecs_client: &EcsClient,
+    task_definition: String,
+) -> Result<TaskDefinition, Error> {
+    helpers::retry_log(format!("Deregistering {}", task_definition), || {
+        ecs_client
+            .deregister_task_definition(DeregisterTaskDefinitionRequest {
+                task_definition: task_definition.clone(),
+            })
+            .sync()
+            .map_err(|e| match e {
+                DeregisterTaskDefinitionError::Unknown(s) => {
+                    if s == r#"{"__type":"ThrottlingException","message":"Rate exceeded"}"# {
+                        backoff::Error::Transient(DeregisterTaskDefinitionError::Unknown(s))
+                    } else {
+                        backoff::Error::Permanent(DeregisterTaskDefinitionError::Unknown(s))
+                    }
+                }
+                _ => backoff::Error::Permanent(e),
+            })
+    })?
+    .task_definition
+    .ok_or(format_err!(
+        "Tried to deregister task definition, but nothing returned"
+    ))
+}
+
+/// The container definition to modify in a request. The container may only be left out when the
+/// task definition has a single container
+pub fn container_mut<'a>(
+    req: &'a mut RegisterTaskDefinitionRequest,
+    container: Option<&str>,
+) -> Result<&'a mut ContainerDefinition, Error> {
+    let names = req
+        .container_definitions
+        .iter()
+        .filter_map(|cd| cd.name.clone())
+        .collect::<Vec<String>>();
+    let container = match container {
+        Some(container) => container.to_string(),
+        None if names.len() == 1 => names[0].clone(),
+        None => bail!(
+            "{} has containers {}, pick one with --container",
+            req.family,
+            names.join(", ")
+        ),
+    };
+
+    let family = req.family.clone();
+    req.container_definitions
+        .iter_mut()
+        .find(|cd| cd.name.as_ref() == Some(&container))
+        .ok_or(format_err!("{} has no container {}", family, container))
+}
+
+/// A copy of a task definition with one container's image replaced, where `image` may be just
+/// a `:<tag>`
+pub fn with_image(
+    task_definition: &TaskDefinition,
+    container: Option<&str>,
+    image: &str,
+) -> Result<RegisterTaskDefinitionRequest, Error> {
+    let mut req = register_request(task_definition)?;
+
+    {
+        let container_definition = container_mut(&mut req, container)?;
+        let current = container_definition.image.clone().unwrap_or_default();
+        container_definition.image = Some(images::replace_image(&current, image)?);
+    }
+
+    Ok(req)
+}
+
+/// Renames, removes and then sets environment variables, returning a description of each
+/// change. Values are left out of the descriptions since they may be sensitive
+pub fn edit_environment(
+    environment: &mut Vec<KeyValuePair>,
+    set: &[Assignment],
+    remove: &[String],
+    rename: &[Assignment],
+) -> Result<Vec<String>, Error> {
+    let position = |environment: &Vec<KeyValuePair>, name: &str| {
+        environment
+            .iter()
+            .position(|kv| kv.name.as_ref().is_some_and(|n| n == name))
+    };
+
+    let mut changes = Vec::new();
+
+    for Assignment {
+        name: from,
+        value: to,
+    } in rename
+    {
+        match (position(environment, from), position(environment, to)) {
+            (Some(_), Some(_)) => bail!("Can't rename {} to {}, which is already set", from, to),
+            (Some(i), None) => {
+                environment[i].name = Some(to.clone());
+                changes.push(format!("renamed {} to {}", from, to));
+            }
+            // Renamed by an earlier run
+            (None, Some(_)) => {}
+            (None, None) => bail!("Can't rename {}, which is not set", from),
+        }
+    }
+
+    for name in remove {
+        if let Some(i) = position(environment, name) {
+            environment.remove(i);
+            changes.push(format!("removed {}", name));
+        }
+    }
+
+    for Assignment { name, value } in set {
+        match position(environment, name) {
+            Some(i) if environment[i].value.as_ref() == Some(value) => {}
+            Some(i) => {
+                environment[i].value = Some(value.clone());
+                changes.push(format!("changed {}", name));
+            }
+            None => {
+                environment.push(KeyValuePair {
+                    name: Some(name.clone()),
+                    value: Some(value.clone()),
+                });
+                changes.push(format!("added {}", name));
+            }
+        }
+    }
+
+    Ok(changes)
+}
+
+/// Task CPU as CPU units, from either units (`1024`) or vCPUs (`1 vCPU`)
+fn task_cpu_units(cpu: &str) -> Result<i64, Error> {
+    let cpu = cpu.trim();
+    match cpu.to_lowercase().strip_suffix("vcpu") {
+        Some(vcpus) => Ok((vcpus.trim().parse::<f64>()? * 1024.0).round() as i64),
+        None => Ok(cpu.parse::<i64>()?),
+    }
+}
+
+/// Task memory in MiB, from either MiB (`2048`) or GB (`2 GB`)
+fn task_memory_mib(memory: &str) -> Result<i64, Error> {
+    let memory = memory.trim();
+    match memory.to_lowercase().strip_suffix("gb") {
+        Some(gb) => Ok((gb.trim().parse::<f64>()? * 1024.0).round() as i64),
+        None => Ok(memory.parse::<i64>()?),
+    }
+}
+
+/// The memory sizes in MiB Fargate allows for a task CPU size
+fn fargate_memory_sizes(cpu: i64) -> Vec<i64> {
+    match cpu {
+        256 => vec![512, 1024, 2048],
+        512 => (1..=4).map(|gb| gb * 1024).collect(),
+        1024 => (2..=8).map(|gb| gb * 1024).collect(),
+        2048 => (4..=16).map(|gb| gb * 1024).collect(),
+        4096 => (8..=30).map(|gb| gb * 1024).collect(),
+        8192 => (16..=60).step_by(4).map(|gb| gb * 1024).collect(),
+        16384 => (32..=120).step_by(8).map(|gb| gb * 1024).collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn or_unset(value: Option<i64>) -> String {
+    value.map_or_else(|| String::from("unset"), |v| v.to_string())
+}
+
+/// Applies CPU and memory changes to the task and one of its containers, returning a
+/// description of each change. Fargate tasks must end up with a valid CPU and memory combination
+pub fn resize(
+    req: &mut RegisterTaskDefinitionRequest,
+    container: Option<&str>,
+    changes: &ResourceChanges,
+) -> Result<Vec<String>, Error> {
+    let mut descriptions = Vec::new();
+
+    let task_cpu = req.cpu.as_ref().map(|c| task_cpu_units(c)).transpose()?;
+    if let Some(amount) = changes.task_cpu {
+        let cpu = amount
+            .resolve(task_cpu)
+            .ok_or(format_err!("{} has no task CPU to scale", req.family))?;
+        if task_cpu != Some(cpu) {
+            descriptions.push(format!("task CPU {} -> {}", or_unset(task_cpu), cpu));
+            req.cpu = Some(cpu.to_string());
+        }
+    }
+
+    let task_memory = req
+        .memory
+        .as_ref()
+        .map(|m| task_memory_mib(m))
+        .transpose()?;
+    if let Some(amount) = changes.task_memory {
+        let memory = amount
+            .resolve(task_memory)
+            .ok_or(format_err!("{} has no task memory to scale", req.family))?;
+        if task_memory != Some(memory) {
+            descriptions.push(format!(
+                "task memory {} -> {}",
+                or_unset(task_memory),
+                memory
+            ));
+            req.memory = Some(memory.to_string());
+        }
+    }
+
+    if changes.changes_container() {
+        let family = req.family.clone();
+        let container_definition = container_mut(req, container)?;
+        let container_name = container_definition.name.clone().unwrap_or_default();
+
+        let fields = [
+            ("CPU", changes.cpu, &mut container_definition.cpu),
+            ("memory", changes.memory, &mut container_definition.memory),
+            (
+                "memory reservation",
+                changes.memory_reservation,
+                &mut container_definition.memory_reservation,
+            ),
+        ];
+        for (label, amount, field) in fields {
+            if let Some(amount) = amount {
+                let value = amount.resolve(*field).ok_or(format_err!(
+                    "{} container {} has no {} to scale",
+                    family,
+                    container_name,
+                    label
+                ))?;
+                if *field != Some(value) {
+                    descriptions.push(format!(
+                        "{} {} {} -> {}",
+                        container_name,
+                        label,
+                        or_unset(*field),
+                        value
+                    ));
+                    *field = Some(value);
+                }
+            }
+        }
+
+        if let (Some(memory), Some(reservation)) = (
+            container_definition.memory,
+            container_definition.memory_reservation,
+        ) {
+            if reservation > memory {
+                bail!(
+                    "{} container {} would reserve {} MiB, more than its {} MiB limit",
+                    family,
+                    container_name,
+                    reservation,
+                    memory
+                );
+            }
+        }
+    }
+
+    let fargate = req
+        .requires_compatibilities
+        .iter()
+        .flat_map(|cs| cs.iter())
+        .any(|c| c == "FARGATE");
+    if fargate && !descriptions.is_empty() {
+        let cpu = req.cpu.as_ref().map(|c| task_cpu_units(c)).transpose()?;
+        let memory = req
+            .memory
+            .as_ref()
+            .map(|m| task_memory_mib(m))
+            .transpose()?;
+        match (cpu, memory) {
+            (Some(cpu), Some(memory)) => {
+                let sizes = fargate_memory_sizes(cpu);
+                if sizes.is_empty() {
+                    bail!(
+                        "{} would have {} CPU units, which Fargate doesn't offer",
+                        req.family,
+                        cpu
+                    );
+                }
+                if !sizes.contains(&memory) {
+                    bail!(
+                        "{} would have {} MiB with {} CPU units, which Fargate doesn't offer. \
+                         Valid sizes are {}",
+                        req.family,
+                        memory,
+                        cpu,
+                        sizes
+                            .iter()
+                            .map(|s| s.to_string())
+                            .collect::<Vec<String>>()
+                            .join(", ")
+                    );
+                }
+            }
+            _ => bail!(
+                "{} runs on Fargate, which needs a task CPU and memory",
+                req.family
+            ),
+        }
+    }
+
+    Ok(descriptions)
+}
+
+/// The active revisions of a family and which of them services deploy
+#[derive(Debug, Clone)]
+pub struct FamilyRevisions {
+    pub family: String,
+    /// Revision number and ARN, oldest first
+    pub revisions: Vec<(i64, String)>,
+    pub in_use: BTreeSet<i64>,
+}
+
+impl FamilyRevisions {
+    /// Revisions no service deploys, other than the newest `keep`, oldest first
+    pub fn unused(&self, keep: usize) -> Vec<&(i64, String)> {
+        let newest = self.revisions.len().saturating_sub(keep);
+        self.revisions[..newest]
+            .iter()
+            .filter(|&&(revision, _)| !self.in_use.contains(&revision))
+            .collect()
+    }
+}
+
+/// The task definitions services are deploying, including those of unfinished deployments
+pub fn task_definitions_in_use(services: &[Service]) -> HashSet<String> {
+    services
+        .iter()
+        .flat_map(|s| {
+            s.task_definition.iter().cloned().chain(
+                s.deployments
+                    .iter()
+                    .flat_map(|ds| ds.iter())
+                    .filter_map(|d| d.task_definition.clone()),
+            )
+        })
+        .collect()
+}
+
+/// Every active task definition in the region, by family
+pub fn family_revisions(
+    ecs_client: &EcsClient,
+    in_use: &HashSet<String>,
+) -> Result<Vec<FamilyRevisions>, Error> {
+    let mut families: BTreeMap<String, FamilyRevisions> = BTreeMap::new();
+
+    for arn in list_task_definitions(ecs_client, None, "ACTIVE")? {
+        let (family, revision) = match task_definition_family_revision(&arn) {
+            Some(family_revision) => family_revision,
+            None => {
+                warn!("Skipping task definition {}, which has no revision", arn);
+                continue;
+            }
+        };
+
+        let entry = families
+            .entry(family.clone())
+            .or_insert_with(|| FamilyRevisions {
+                family,
+                revisions: Vec::new(),
+                in_use: BTreeSet::new(),
+            });
+        if in_use.contains(&arn) {
+            entry.in_use.insert(revision);
+        }
+        entry.revisions.push((revision, arn));
+    }
+
+    Ok(families
+        .into_values()
+        .map(|mut family| {
+            family.revisions.sort();
+            family
+        })
+        .collect())
+}
+
+/// Flattens JSON into dotted paths. Lists of named objects, such as environment variables,
+/// are keyed by name so reordering them is not reported as a change
+pub fn flatten(path: &str, value: &Value, flat: &mut BTreeMap<String, String>) {
+    match *value {
+        Value::Object(ref map) => {
+            for (key, value) in map {
+                let path = if path.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{}.{}", path, key)
+                };
+                flatten(&path, value, flat);
+            }
+        }
+        Value::Array(ref items) => {
+            let named = items
+                .iter()
+                .all(|item| item.get("name").is_some_and(Value::is_string));
+            for (i, item) in items.iter().enumerate() {
+                let key = match item.get("name").and_then(Value::as_str) {
+                    Some(name) if named => name.to_string(),
+                    _ => i.to_string(),
+                };
+                flatten(&format!("{}[{}]", path, key), item, flat);
+            }
+        }
+        Value::Null => {}
+        _ => {
+            flat.insert(path.to_string(), value.to_string());
+        }
+    }
+}
+
+/// The paths whose values differ between two flattened documents, as `path: a -> b`
+pub fn diff_flattened(a: &BTreeMap<String, String>, b: &BTreeMap<String, String>) -> Vec<String> {
+    let unset = String::from("(unset)");
+
+    a.keys()
+        .chain(b.keys())
+        .collect::<BTreeSet<&String>>()
+        .into_iter()
+        .filter_map(|path| {
+            let a_value = a.get(path).unwrap_or(&unset);
+            let b_value = b.get(path).unwrap_or(&unset);
+            if a_value == b_value {
+                None
+            } else {
+                Some(format!("{}: {} -> {}", path, a_value, b_value))
+            }
+        })
+        .collect()
+}
+
+fn flattened_container(
+    container_definition: &ContainerDefinition,
+) -> Result<BTreeMap<String, String>, Error> {
+    let mut flat = BTreeMap::new();
+    flatten("", &serde_json::to_value(container_definition)?, &mut flat);
+    Ok(flat)
+}
+
+/// The differences between two task definitions: the task-level settings first, then each
+/// container by name. Sections without differences are left out
+pub fn diff(a: &TaskDefinition, b: &TaskDefinition) -> Result<Vec<(String, Vec<String>)>, Error> {
+    diff_requests(&register_request(a)?, &register_request(b)?)
+}
+
+/// Like `diff`, for task definitions that may not be registered yet
+pub fn diff_requests(
+    a: &RegisterTaskDefinitionRequest,
+    b: &RegisterTaskDefinitionRequest,
+) -> Result<Vec<(String, Vec<String>)>, Error> {
+    let task_settings =
+        |req: &RegisterTaskDefinitionRequest| -> Result<BTreeMap<String, String>, Error> {
+            let req = RegisterTaskDefinitionRequest {
+                container_definitions: Vec::new(),
+                ..req.clone()
+            };
+
+            let mut flat = BTreeMap::new();
+            flatten("", &serde_json::to_value(&req)?, &mut flat);
+            Ok(flat)
+        };
+    let containers = |req: &RegisterTaskDefinitionRequest| {
+        req.container_definitions
+            .iter()
+            .map(|cd| (cd.name.clone().unwrap_or_default(), cd.clone()))
+            .collect::<BTreeMap<String, ContainerDefinition>>()
+    };
+
+    let mut sections = vec![(
+        String::from("Task"),
+        diff_flattened(&task_settings(a)?, &task_settings(b)?),
+    )];
+
+    let (a_containers, b_containers) = (containers(a), containers(b));
+    for name in a_containers
+        .keys()
+        .chain(b_containers.keys())
+        .collect::<BTreeSet<&String>>()
+    {
+        let changes = match (a_containers.get(name), b_containers.get(name)) {
+            (Some(a_cd), Some(b_cd)) => {
+                diff_flattened(&flattened_container(a_cd)?, &flattened_container(b_cd)?)
+            }
+            (Some(_), None) => vec![String::from("Removed")],
+            (None, Some(_)) => vec![String::from("Added")],
+            (None, None) => Vec::new(),
+        };
+        sections.push((format!("Container {}", name), changes));
+    }
+
+    Ok(sections
+        .into_iter()
+        .filter(|(_, changes)| !changes.is_empty())
+        .collect())
+}
+
+/// Clears container settings that ECS fills in with their default values when a task
+/// definition is registered, so a definition written by hand compares equal to the
+/// registered one
+pub fn strip_defaults(req: &mut RegisterTaskDefinitionRequest) {
+    let awsvpc = req.network_mode.as_deref() == Some("awsvpc");
+
+    for cd in &mut req.container_definitions {
+        if cd.essential == Some(true) {
+            cd.essential = None;
+        }
+        if cd.cpu == Some(0) {
+            cd.cpu = None;
+        }
+        for port_mapping in cd.port_mappings.iter_mut().flat_map(|pms| pms.iter_mut()) {
+            if port_mapping.protocol.as_deref() == Some("tcp") {
+                port_mapping.protocol = None;
+            }
+            if awsvpc && port_mapping.host_port == port_mapping.container_port {
+                port_mapping.host_port = None;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rusoto_ecs::ContainerDefinition;
+    use std::str::FromStr;
+
+    use args::Amount;
+
+    fn fargate_request(cpu: &str, memory: &str) -> RegisterTaskDefinitionRequest {
+        RegisterTaskDefinitionRequest {
+            family: String::from("app"),
+            cpu: Some(cpu.to_string()),
+            memory: Some(memory.to_string()),
+            requires_compatibilities: Some(vec![String::from("FARGATE")]),
+            container_definitions: vec![ContainerDefinition {
+                name: Some(String::from("web")),
+                cpu: Some(256),
+                memory: Some(512),
+                memory_reservation: Some(256),
+                ..Default::default()
+            }],
+            ..Default::default()
+        }
+    }
+
+    fn amount(s: &str) -> Option<Amount> {
+        Some(Amount::from_str(s).unwrap())
+    }
+
+    #[test]
+    fn fargate_memory_sizes_per_cpu() {
+        assert_eq!(fargate_memory_sizes(256), vec![512, 1024, 2048]);
+        assert_eq!(fargate_memory_sizes(512), vec![1024, 2048, 3072, 4096]);
+        assert_eq!(fargate_memory_sizes(1024).first(), Some(&2048));
+        assert_eq!(fargate_memory_sizes(1024).last(), Some(&8192));
+        assert_eq!(fargate_memory_sizes(4096).len(), 23);
+        assert_eq!(fargate_memory_sizes(8192).first(), Some(&16384));
+        assert!(fargate_memory_sizes(8192).contains(&20480));
+        assert!(!fargate_memory_sizes(8192).contains(&17408));
+        assert_eq!(fargate_memory_sizes(16384).last(), Some(&122880));
+        assert!(fargate_memory_sizes(300).is_empty());
+    }
+
+    #[test]
+    fn task_sizes() {
+        assert_eq!(task_cpu_units("1024").unwrap(), 1024);
+        assert_eq!(task_cpu_units("0.5 vCPU").unwrap(), 512);
+        assert_eq!(task_memory_mib("2048").unwrap(), 2048);
+        assert_eq!(task_memory_mib("2 GB").unwrap(), 2048);
+        assert!(task_memory_mib("lots").is_err());
+    }
+
+    #[test]
+    fn resize_task_and_container() {
+        let mut req = fargate_request("256", "512");
+        let changes = ResourceChanges {
+            task_cpu: amount("512"),
+            task_memory: amount("200%"),
+            memory: amount("150%"),
+            ..Default::default()
+        };
+
+        let descriptions = resize(&mut req, None, &changes).unwrap();
+
+        assert_eq!(
+            descriptions,
+            vec![
+                "task CPU 256 -> 512",
+                "task memory 512 -> 1024",
+                "web memory 512 -> 768",
+            ]
+        );
+        assert_eq!(req.cpu, Some(String::from("512")));
+        assert_eq!(req.memory, Some(String::from("1024")));
+        assert_eq!(req.container_definitions[0].memory, Some(768));
+        assert_eq!(req.container_definitions[0].cpu, Some(256));
+    }
+
+    #[test]
+    fn resize_without_changes() {
+        let mut req = fargate_request("1 vCPU", "2 GB");
+        let changes = ResourceChanges {
+            task_cpu: amount("1024"),
+            task_memory: amount("100%"),
+            ..Default::default()
+        };
+
+        assert!(resize(&mut req, None, &changes).unwrap().is_empty());
+        assert_eq!(req.cpu, Some(String::from("1 vCPU")));
+    }
+
+    #[test]
+    fn resize_rejects_invalid_fargate_sizes() {
+        let mut req = fargate_request("256", "512");
+        let changes = ResourceChanges {
+            task_memory: amount("4096"),
+            ..Default::default()
+        };
+        assert!(resize(&mut req, None, &changes).is_err());
+
+        let mut req = fargate_request("256", "512");
+        let changes = ResourceChanges {
+            task_cpu: amount("300"),
+            ..Default::default()
+        };
+        assert!(resize(&mut req, None, &changes).is_err());
+
+        // EC2 tasks aren't limited to Fargate's sizes
+        let mut req = RegisterTaskDefinitionRequest {
+            requires_compatibilities: None,
+            ..fargate_request("256", "512")
+        };
+        assert!(resize(&mut req, None, &changes).is_ok());
+    }
+
+    #[test]
+    fn resize_rejects_reservations_above_the_limit() {
+        let mut req = fargate_request("256", "512");
+        let changes = ResourceChanges {
+            memory_reservation: amount("1024"),
+            ..Default::default()
+        };
+        assert!(resize(&mut req, None, &changes).is_err());
+    }
+
+    #[test]
+    fn resize_needs_a_value_to_scale() {
+        let mut req = RegisterTaskDefinitionRequest {
+            cpu: None,
+            requires_compatibilities: None,
+            ..fargate_request("256", "512")
+        };
+        let changes = ResourceChanges {
+            task_cpu: amount("150%"),
+            ..Default::default()
+        };
+        assert!(resize(&mut req, None, &changes).is_err());
+    }
+
+    #[test]
+    fn amounts() {
+        assert!(Amount::from_str("0").is_err());
+        assert!(Amount::from_str("-5%").is_err());
+        assert!(Amount::from_str("inf%").is_err());
+        assert!(Amount::from_str("nan%").is_err());
+        assert_eq!(
+            Amount::from_str("50%").unwrap().resolve(Some(1001)),
+            Some(501)
+        );
+        assert_eq!(Amount::from_str("50%").unwrap().resolve(None), None);
+    }
+
+    fn environment(pairs: &[(&str, &str)]) -> Vec<KeyValuePair> {
+        pairs
+            .iter()
+            .map(|&(name, value)| KeyValuePair {
+                name: Some(name.to_string()),
+                value: Some(value.to_string()),
+            })
+            .collect()
+    }
+
+    fn assignments(pairs: &[&str]) -> Vec<Assignment> {
+        pairs
+            .iter()
+            .map(|s| Assignment::from_str(s).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn renames_then_removes_then_sets() {
+        let mut env = environment(&[("A", "1"), ("B", "2"), ("C", "3")]);
+
+        let changes = edit_environment(
+            &mut env,
+            &assignments(&["A=4", "D=5"]),
+            &[String::from("B"), String::from("D")],
+            &assignments(&["B=D"]),
+        )
+        .unwrap();
+
+        assert_eq!(
+            changes,
+            vec!["renamed B to D", "removed D", "changed A", "added D",]
+        );
+        assert_eq!(env, environment(&[("A", "4"), ("C", "3"), ("D", "5")]));
+    }
+
+    #[test]
+    fn unchanged_environments() {
+        let mut env = environment(&[("A", "1"), ("B", "2")]);
+
+        let changes = edit_environment(
+            &mut env,
+            &assignments(&["A=1"]),
+            &[String::from("C")],
+            &assignments(&["C=B"]),
+        )
+        .unwrap();
+
+        assert!(changes.is_empty(), "{:?}", changes);
+        assert_eq!(env, environment(&[("A", "1"), ("B", "2")]));
+    }
+
+    #[test]
+    fn invalid_renames() {
+        let mut env = environment(&[("A", "1"), ("B", "2")]);
+
+        assert!(edit_environment(&mut env, &[], &[], &assignments(&["A=B"])).is_err());
+        assert!(edit_environment(&mut env, &[], &[], &assignments(&["C=D"])).is_err());
+        assert_eq!(env, environment(&[("A", "1"), ("B", "2")]));
+    }
+
+    fn family(revisions: &[i64], in_use: &[i64]) -> FamilyRevisions {
+        FamilyRevisions {
+            family: String::from("app"),
+            revisions: revisions
+                .iter()
+                .map(|&revision| (revision, format!("app:{}", revision)))
+                .collect(),
+            in_use: in_use.iter().cloned().collect(),
+        }
+    }
+
+    fn revision_numbers(revisions: Vec<&(i64, String)>) -> Vec<i64> {
+        revisions
+            .into_iter()
+            .map(|&(revision, _)| revision)
+            .collect()
+    }
+
+    #[test]
+    fn unused_revisions() {
+        let revisions = family(&[1, 2, 3, 4, 5], &[2, 5]);
+
+        assert_eq!(revision_numbers(revisions.unused(0)), vec![1, 3, 4]);
+        assert_eq!(revision_numbers(revisions.unused(2)), vec![1, 3]);
+        assert_eq!(revision_numbers(revisions.unused(5)), Vec::<i64>::new());
+        assert_eq!(revision_numbers(revisions.unused(10)), Vec::<i64>::new());
+        assert_eq!(
+            revision_numbers(family(&[], &[]).unused(1)),
+            Vec::<i64>::new()
+        );
+    }
+
+    #[test]
+    fn flattens_named_lists_by_name() {
+        let mut flat = BTreeMap::new();
+        flatten(
+            "",
+            &json!({
+                "image": "nginx",
+                "memory": 512,
+                "command": ["run", "--fast"],
+                "environment": [{"name": "B", "value": "2"}, {"name": "A", "value": "1"}],
+                "healthCheck": null,
+            }),
+            &mut flat,
+        );
+
+        assert_eq!(
+            flat.into_iter().collect::<Vec<(String, String)>>(),
+            vec![
+                (String::from("command[0]"), String::from("\"run\"")),
+                (String::from("command[1]"), String::from("\"--fast\"")),
+                (String::from("environment[A].name"), String::from("\"A\"")),
+                (String::from("environment[A].value"), String::from("\"1\"")),
+                (String::from("environment[B].name"), String::from("\"B\"")),
+                (String::from("environment[B].value"), String::from("\"2\"")),
+                (String::from("image"), String::from("\"nginx\"")),
+                (String::from("memory"), String::from("512")),
+            ]
+        );
+    }
+
+    #[test]
+    fn diffs_flattened_documents() {
+        let flattened = |value: Value| {
+            let mut flat = BTreeMap::new();
+            flatten("", &value, &mut flat);
+            flat
+        };
+        let a = flattened(json!({
+            "image": "nginx:1",
+            "memory": 512,
+            "environment": [{"name": "A", "value": "1"}, {"name": "B", "value": "2"}],
+        }));
+        let b = flattened(json!({
+            "image": "nginx:2",
+            "cpu": 256,
+            "environment": [{"name": "B", "value": "2"}, {"name": "A", "value": "1"}],
+        }));
+
+        assert_eq!(
+            diff_flattened(&a, &b),
+            vec![
+                "cpu: (unset) -> 256",
+                "image: \"nginx:1\" -> \"nginx:2\"",
+                "memory: 512 -> (unset)",
+            ]
+        );
+        assert!(diff_flattened(&a, &a).is_empty());
+    }
+}
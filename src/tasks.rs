@@ -0,0 +1,402 @@
+use backoff;
+use failure::Error;
+use rusoto_ecs::{
+    DescribeTasksError, DescribeTasksRequest, Ecs, EcsClient, ListTasksError, ListTasksRequest,
+    StopTaskError, StopTaskRequest, Task,
+};
+use std::collections::HashMap;
+
+use args::TaskFilter;
+use helpers;
+use services;
+
+/// The task id at the end of a task ARN
+pub fn task_id(task: &Task) -> String {
+    task.task_arn
+        .as_ref()
+        .and_then(|arn| arn.rsplit('/').next())
+        .unwrap_or_default()
+        .to_string()
+}
+
+/// The name of the service that started a task, from its `service:<name>` group
+pub fn task_service_name(task: &Task) -> Option<&str> {
+    task.group
+        .as_ref()
+        .and_then(|group| group.strip_prefix("service:"))
+}
+
+/// Counts of each distinct value, most frequent first
+fn histogram<I>(values: I) -> Vec<(String, usize)>
+where
+    I: IntoIterator<Item = String>,
+{
+    let mut counts: HashMap<String, usize> = HashMap::new();
+    for value in values {
+        *counts.entry(value).or_insert(0) += 1;
+    }
+
+    let mut histogram = counts.into_iter().collect::<Vec<(String, usize)>>();
+    histogram.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    histogram
+}
+
+/// Groups stopped tasks by why they stopped: the task's stop reason, each container's exit code
+/// and reason, and the task's health when it stopped. Reasons are cut at the first `(` or `:`
+/// so that e.g. every `OutOfMemoryError: Container killed ...` lands in one bucket
+pub fn stopped_task_histograms(tasks: &[Task]) -> Vec<(&'static str, Vec<(String, usize)>)> {
+    let bucket = |reason: &str| {
+        reason
+            .split(['(', ':'])
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .to_string()
+    };
+    let containers = || {
+        tasks
+            .iter()
+            .flat_map(|t| t.containers.iter().flat_map(|cs| cs.iter()))
+    };
+
+    vec![
+        (
+            "Stopped reasons",
+            histogram(
+                tasks
+                    .iter()
+                    .filter_map(|t| t.stopped_reason.as_ref())
+                    .map(|r| bucket(r)),
+            ),
+        ),
+        (
+            "Container exit codes",
+            histogram(containers().filter_map(|c| {
+                c.exit_code
+                    .map(|code| format!("{} exited {}", c.name.clone().unwrap_or_default(), code))
+            })),
+        ),
+        (
+            "Container reasons",
+            histogram(
+                containers()
+                    .filter_map(|c| c.reason.as_ref())
+                    .map(|r| bucket(r)),
+            ),
+        ),
+        (
+            "Health at stop",
+            histogram(tasks.iter().filter_map(|t| t.health_status.clone())),
+        ),
+    ]
+}
+
+/// One line describing a task's status, health, stop reason and container exit codes
+pub fn task_summary(task: &Task) -> String {
+    let mut summary = format!(
+        "{} - {} - {} - Health: {} - Task: {}",
+        task_id(task),
+        task.group.clone().unwrap_or_default(),
+        task.last_status.clone().unwrap_or_default(),
+        task.health_status.clone().unwrap_or_default(),
+        task.task_definition_arn
+            .as_ref()
+            .and_then(|arn| arn.rsplit('/').next())
+            .unwrap_or_default(),
+    );
+
+    if let Some(ref stopped_reason) = task.stopped_reason {
+        summary.push_str(&format!(" - Stopped: {}", stopped_reason));
+    }
+
+    let exit_codes = task
+        .containers
+        .iter()
+        .flat_map(|cs| cs.iter())
+        .filter_map(|c| {
+            c.exit_code
+                .map(|code| format!("{}={}", c.name.clone().unwrap_or_default(), code))
+        })
+        .collect::<Vec<String>>();
+    if !exit_codes.is_empty() {
+        summary.push_str(&format!(" - Exit codes: {}", exit_codes.join(", ")));
+    }
+
+    summary
+}
+
+pub fn list_tasks(
+    ecs_client: &EcsClient,
+    cluster: String,
+    service: Option<String>,
+    container_instance: Option<String>,
+    desired_status: &str,
+) -> Result<Vec<String>, Error> {
+    let mut token = Some(String::new());
+
+    let mut tasks = Vec::new();
+
+    while token.is_some() {
+        let res = helpers::retry_log(format!("listing tasks in {}", cluster), || {
+            ecs_client
+                .list_tasks(ListTasksRequest {
+                    cluster: Some(cluster.clone()),
+                    container_instance: container_instance.clone(),
+                    desired_status: Some(desired_status.to_string()),
+                    family: None,
+                    launch_type: None,
+                    max_results: None,
+                    next_token: token.clone(),
+                    service_name: service.clone(),
+                    started_by: None,
+                })
+                .sync()
+                .map_err(|e| match e {
+                    ListTasksError::Unknown(s) => {
+                        if s == r#"{"__type":"ThrottlingException","message":"Rate exceeded"}"# {
+                            backoff::Error::Transient(ListTasksError::Unknown(s))
+                        } else {
+                            backoff::Error::Permanent(ListTasksError::Unknown(s))
+                        }
+                    }
+                    _ => backoff::Error::Permanent(e),
+                })
+        })?;
+        if let Some(mut arns) = res.task_arns {
+            tasks.append(&mut arns)
+        };
+
+        token = res.next_token;
+    }
+
+    Ok(tasks)
+}
+
+pub fn describe_tasks(
+    ecs_client: &EcsClient,
+    cluster: String,
+    task_arns: &[String],
+) -> Result<Vec<Task>, Error> {
+    let mut tasks = Vec::new();
+
+    // DescribeTasks takes at most 100 tasks per call
+    for chunk in task_arns.chunks(100) {
+        let res = helpers::retry_log(format!("Describing tasks in {}", cluster), || {
+            ecs_client
+                .describe_tasks(DescribeTasksRequest {
+                    cluster: Some(cluster.clone()),
+                    tasks: chunk.to_vec(),
+                })
+                .sync()
+                .map_err(|e| match e {
+                    DescribeTasksError::Unknown(s) => {
+                        if s == r#"{"__type":"ThrottlingException","message":"Rate exceeded"}"# {
+                            backoff::Error::Transient(DescribeTasksError::Unknown(s))
+                        } else {
+                            backoff::Error::Permanent(DescribeTasksError::Unknown(s))
+                        }
+                    }
+                    _ => backoff::Error::Permanent(e),
+                })
+        })?;
+
+        if let Some(failures) = res.failures {
+            if !failures.is_empty() {
+                bail!("Failures: {:?}", failures);
+            }
+        }
+
+        if let Some(mut described) = res.tasks {
+            tasks.append(&mut described);
+        }
+    }
+
+    Ok(tasks)
+}
+
+pub fn stop_task(
+    ecs_client: &EcsClient,
+    cluster: String,
+    task: &Task,
+    reason: String,
+) -> Result<Task, Error> {
+    let task_arn = task
+        .task_arn
+        .clone()
+        .ok_or(format_err!("Task has no ARN"))?;
+
+    helpers::retry_log(format!("Stopping {}/{}", cluster, task_id(task)), || {
+        ecs_client
+            .stop_task(StopTaskRequest {
+                cluster: Some(cluster.clone()),
+                reason: Some(reason.clone()),
+                task: task_arn.clone(),
+            })
+            .sync()
+            .map_err(|e| match e {
+                StopTaskError::Unknown(s) => {
+                    if s == r#"{"__type":"ThrottlingException","message":"Rate exceeded"}"# {
+                        backoff::Error::Transient(StopTaskError::Unknown(s))
+                    } else {
+                        backoff::Error::Permanent(StopTaskError::Unknown(s))
+                    }
+                }
+                _ => backoff::Error::Permanent(e),
+            })
+    })?
+    .task
+    .ok_or(format_err!("Tried to stop task, but nothing returned"))
+}
+
+/// The running tasks in a cluster that match every given filter
+pub fn filter_tasks(
+    ecs_client: &EcsClient,
+    cluster: String,
+    filter: &TaskFilter,
+) -> Result<Vec<Task>, Error> {
+    let task_arns = list_tasks(
+        ecs_client,
+        cluster.clone(),
+        filter.service.clone(),
+        filter.container_instance.clone(),
+        "RUNNING",
+    )?;
+    let tasks = describe_tasks(ecs_client, cluster.clone(), &task_arns)?;
+
+    // The task definition each task's service currently deploys, by service name
+    let mut current_task_definitions: HashMap<String, Option<String>> = HashMap::new();
+
+    let mut matching = Vec::new();
+    for task in tasks {
+        if let Some(hours) = filter.older_than_hours {
+            let started_at = task.started_at.or(task.created_at);
+            if started_at.is_none_or(|t| helpers::minutes_since(t) < hours * 60.0) {
+                continue;
+            }
+        }
+
+        if filter.old_revision {
+            let service_name = match task_service_name(&task) {
+                Some(service_name) => service_name.to_string(),
+                None => continue,
+            };
+
+            if !current_task_definitions.contains_key(&service_name) {
+                let service =
+                    services::describe_service(ecs_client, cluster.clone(), service_name.clone())?;
+                current_task_definitions.insert(service_name.clone(), service.task_definition);
+            }
+
+            if current_task_definitions[&service_name] == task.task_definition_arn {
+                continue;
+            }
+        }
+
+        matching.push(task);
+    }
+
+    Ok(matching)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rusoto_ecs::Container;
+
+    fn counts(counts: &[(&str, usize)]) -> Vec<(String, usize)> {
+        counts
+            .iter()
+            .map(|&(value, count)| (value.to_string(), count))
+            .collect()
+    }
+
+    fn stopped_task(reason: &str, exit_code: i64, container_reason: Option<&str>) -> Task {
+        Task {
+            stopped_reason: Some(reason.to_string()),
+            health_status: Some(String::from("UNHEALTHY")),
+            containers: Some(vec![Container {
+                name: Some(String::from("web")),
+                exit_code: Some(exit_code),
+                reason: container_reason.map(String::from),
+                ..Default::default()
+            }]),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn histograms_sort_by_count_then_value() {
+        let values = vec!["b", "a", "c", "b", "c", "b"];
+
+        assert_eq!(
+            histogram(values.into_iter().map(String::from)),
+            counts(&[("b", 3), ("c", 2), ("a", 1)])
+        );
+        assert_eq!(
+            histogram(vec![String::from("z"), String::from("y")]),
+            counts(&[("y", 1), ("z", 1)])
+        );
+        assert!(histogram(Vec::new()).is_empty());
+    }
+
+    #[test]
+    fn buckets_stopped_tasks() {
+        let tasks = vec![
+            stopped_task(
+                "Essential container in task exited",
+                137,
+                Some("OutOfMemoryError: Container killed due to memory usage"),
+            ),
+            stopped_task(
+                "Essential container in task exited",
+                137,
+                Some("OutOfMemoryError: Container killed due to memory usage"),
+            ),
+            stopped_task(
+                "Task failed ELB health checks in (target-group arn:aws:...)",
+                143,
+                None,
+            ),
+            stopped_task(
+                "Scaling activity initiated by (deployment ecs-svc/123)",
+                0,
+                None,
+            ),
+            stopped_task("CannotPullContainerError: pull access denied", 0, None),
+        ];
+
+        let histograms = stopped_task_histograms(&tasks);
+
+        assert_eq!(
+            histograms
+                .iter()
+                .map(|&(name, _)| name)
+                .collect::<Vec<&str>>(),
+            vec![
+                "Stopped reasons",
+                "Container exit codes",
+                "Container reasons",
+                "Health at stop"
+            ]
+        );
+        assert_eq!(
+            histograms[0].1,
+            counts(&[
+                ("Essential container in task exited", 2),
+                ("CannotPullContainerError", 1),
+                ("Scaling activity initiated by", 1),
+                ("Task failed ELB health checks in", 1),
+            ])
+        );
+        assert_eq!(
+            histograms[1].1,
+            counts(&[
+                ("web exited 0", 2),
+                ("web exited 137", 2),
+                ("web exited 143", 1)
+            ])
+        );
+        assert_eq!(histograms[2].1, counts(&[("OutOfMemoryError", 2)]));
+        assert_eq!(histograms[3].1, counts(&[("UNHEALTHY", 5)]));
+    }
+}